@@ -0,0 +1,87 @@
+// Throughput per key should stay flat as files grow, anything else means
+// a lookup went quadratic. Run with `cargo bench`.
+
+use criterion::{BenchmarkId, Criterion, Throughput, criterion_group, criterion_main};
+use envq::EnvFile;
+use std::hint::black_box;
+
+const SIZES: [usize; 3] = [1_000, 10_000, 50_000];
+
+fn content(keys: usize) -> String {
+    (0..keys)
+        .map(|i| format!("FEATURE_FLAG_{}=true # flag {}\n", i, i))
+        .collect()
+}
+
+fn list(c: &mut Criterion) {
+    let mut group = c.benchmark_group("list");
+    for keys in SIZES {
+        let env = EnvFile::parse(&content(keys)).unwrap();
+        group.throughput(Throughput::Elements(keys as u64));
+        group.bench_with_input(BenchmarkId::from_parameter(keys), &env, |b, env| {
+            b.iter(|| black_box(env.list_values()))
+        });
+    }
+    group.finish();
+}
+
+fn get_every_key(c: &mut Criterion) {
+    let mut group = c.benchmark_group("get_every_key");
+    for keys in SIZES {
+        let env = EnvFile::parse(&content(keys)).unwrap();
+        group.throughput(Throughput::Elements(keys as u64));
+        group.bench_with_input(BenchmarkId::from_parameter(keys), &env, |b, env| {
+            b.iter(|| {
+                for key in env.list_keys() {
+                    black_box(env.get_value(key));
+                    black_box(env.get_comment(key));
+                }
+            })
+        });
+    }
+    group.finish();
+}
+
+fn set_every_key(c: &mut Criterion) {
+    let mut group = c.benchmark_group("set_every_key");
+    for keys in SIZES {
+        let names: Vec<String> = (0..keys).map(|i| format!("FEATURE_FLAG_{}", i)).collect();
+        group.throughput(Throughput::Elements(keys as u64));
+        group.bench_with_input(BenchmarkId::from_parameter(keys), &names, |b, names| {
+            b.iter(|| {
+                // appends every key, then updates it in place
+                let mut env = EnvFile::parse("").unwrap();
+                for name in names {
+                    env.set_value(name, "true").unwrap();
+                    env.set_comment(name, "flag");
+                }
+                black_box(env)
+            })
+        });
+    }
+    group.finish();
+}
+
+fn parse_and_duplicates(c: &mut Criterion) {
+    let mut group = c.benchmark_group("parse_and_duplicates");
+    for keys in SIZES {
+        let content = content(keys);
+        group.throughput(Throughput::Elements(keys as u64));
+        group.bench_with_input(BenchmarkId::from_parameter(keys), &content, |b, content| {
+            b.iter(|| {
+                let env = EnvFile::parse(content).unwrap();
+                black_box(env.duplicates().len())
+            })
+        });
+    }
+    group.finish();
+}
+
+criterion_group!(
+    benches,
+    list,
+    get_every_key,
+    set_every_key,
+    parse_and_duplicates
+);
+criterion_main!(benches);
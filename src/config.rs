@@ -0,0 +1,83 @@
+use anyhow::{Context, Result};
+use serde::Deserialize;
+use std::path::{Path, PathBuf};
+
+use envq::ParseOptions;
+
+use crate::{Dialects, Duplicates, Keys, Quotes};
+
+const FILE_NAME: &str = ".envq.toml";
+
+/// Per-project defaults from the closest `.envq.toml`, flags on the command
+/// line take precedence.
+#[derive(Default, Deserialize)]
+#[serde(default, deny_unknown_fields)]
+pub struct Config {
+    pub lenient: bool,
+    /// which key names are valid
+    pub keys: Option<Keys>,
+    /// the program that loads the env files
+    pub dialect: Option<Dialects>,
+    /// which definition of a duplicated key `get` reads and `dedupe` keeps
+    pub duplicates: Option<Duplicates>,
+    pub format: FormatConfig,
+    pub lint: LintConfig,
+}
+
+#[derive(Default, Deserialize)]
+#[serde(default, deny_unknown_fields)]
+pub struct FormatConfig {
+    pub quote: Option<Quotes>,
+    pub align_comments: bool,
+    pub collapse_blank_lines: bool,
+    pub uppercase_keys: bool,
+}
+
+#[derive(Default, Deserialize)]
+#[serde(default, deny_unknown_fields)]
+pub struct LintConfig {
+    /// rule ids to skip
+    pub disable: Vec<String>,
+}
+
+impl Config {
+    /// Looks for `.envq.toml` in the directory of the env file (or the working
+    /// directory for stdin) and then in every parent, like rustfmt does.
+    pub fn find(file: Option<&str>) -> Result<Self> {
+        let start = match file.and_then(|file| Path::new(file).parent()) {
+            Some(dir) if !dir.as_os_str().is_empty() => std::path::absolute(dir)?,
+            _ => std::env::current_dir()?,
+        };
+        match find_file(&start) {
+            Some(path) => Self::load(&path),
+            None => Ok(Config::default()),
+        }
+    }
+
+    /// Combines the parse flags of the command line with the config.
+    pub fn parse_options(
+        &self,
+        lenient: Option<bool>,
+        keys: Option<Keys>,
+        dialect: Option<Dialects>,
+    ) -> ParseOptions {
+        let mut options = ParseOptions::default();
+        options.lenient = lenient.unwrap_or(self.lenient);
+        options.key_syntax = keys.or(self.keys).map(Into::into);
+        options.dialect = dialect.or(self.dialect).map(Into::into).unwrap_or_default();
+        options
+    }
+
+    fn load(path: &Path) -> Result<Self> {
+        let content = std::fs::read_to_string(path)
+            .with_context(|| format!("Failed to read {}", path.display()))?;
+        toml::from_str(&content).with_context(|| format!("Invalid config {}", path.display()))
+    }
+}
+
+fn find_file(start: &Path) -> Option<PathBuf> {
+    start
+        .ancestors()
+        .map(|dir| dir.join(FILE_NAME))
+        .find(|path| path.is_file())
+}
@@ -0,0 +1,327 @@
+use std::collections::HashMap;
+use std::fmt;
+use std::str::FromStr;
+
+use serde::de::value::{BorrowedStrDeserializer, SeqDeserializer};
+use serde::de::{
+    self, Deserialize, DeserializeOwned, DeserializeSeed, IntoDeserializer, MapAccess, Visitor,
+};
+use serde::forward_to_deserialize_any;
+
+use crate::convert::parse_bool;
+use crate::env_file::EnvFile;
+use crate::error::ParseError;
+
+/// An error while deserializing a type from an env file.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum DeserializeError {
+    /// the file isn't valid env syntax
+    Parse(ParseError),
+    /// a key is missing or its value doesn't fit the target type
+    Message(String),
+}
+
+impl fmt::Display for DeserializeError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            DeserializeError::Parse(e) => write!(f, "{}", e),
+            DeserializeError::Message(message) => write!(f, "{}", message),
+        }
+    }
+}
+
+impl std::error::Error for DeserializeError {}
+
+impl de::Error for DeserializeError {
+    fn custom<T: fmt::Display>(msg: T) -> Self {
+        DeserializeError::Message(msg.to_string())
+    }
+}
+
+impl From<ParseError> for DeserializeError {
+    fn from(e: ParseError) -> Self {
+        DeserializeError::Parse(e)
+    }
+}
+
+/// Deserializes a type from the content of an env file.
+///
+/// Keys are matched against field names as they are, so structs usually want
+/// `#[serde(rename_all = "SCREAMING_SNAKE_CASE")]`. Like dotenv loaders, the
+/// last definition of a duplicated key wins. Values are converted to the
+/// field types, lists are comma separated.
+pub fn from_str<T: DeserializeOwned>(content: &str) -> Result<T, DeserializeError> {
+    from_env_file(&EnvFile::parse(content)?)
+}
+
+/// Deserializes a type from the keys of a parsed env file.
+pub fn from_env_file<'a, T: Deserialize<'a>>(env_file: &'a EnvFile) -> Result<T, DeserializeError> {
+    let mut pairs: Vec<(&str, &str)> = Vec::new();
+    let mut positions = HashMap::new();
+    for entry in env_file.iter() {
+        match positions.get(entry.key()) {
+            Some(&pos) => pairs[pos] = (entry.key(), entry.value()),
+            None => {
+                positions.insert(entry.key(), pairs.len());
+                pairs.push((entry.key(), entry.value()));
+            }
+        }
+    }
+    T::deserialize(Deserializer {
+        pairs: pairs.into_iter(),
+        value: None,
+    })
+}
+
+struct Deserializer<'a> {
+    pairs: std::vec::IntoIter<(&'a str, &'a str)>,
+    // the pair whose key was handed out last
+    value: Option<(&'a str, &'a str)>,
+}
+
+impl<'de> de::Deserializer<'de> for Deserializer<'de> {
+    type Error = DeserializeError;
+
+    fn deserialize_any<V: Visitor<'de>>(self, visitor: V) -> Result<V::Value, Self::Error> {
+        visitor.visit_map(self)
+    }
+
+    forward_to_deserialize_any! {
+        bool i8 i16 i32 i64 i128 u8 u16 u32 u64 u128 f32 f64 char str string
+        bytes byte_buf option unit unit_struct newtype_struct seq tuple
+        tuple_struct map struct enum identifier ignored_any
+    }
+}
+
+impl<'de> MapAccess<'de> for Deserializer<'de> {
+    type Error = DeserializeError;
+
+    fn next_key_seed<K: DeserializeSeed<'de>>(
+        &mut self,
+        seed: K,
+    ) -> Result<Option<K::Value>, Self::Error> {
+        match self.pairs.next() {
+            Some(pair) => {
+                self.value = Some(pair);
+                seed.deserialize(BorrowedStrDeserializer::new(pair.0))
+                    .map(Some)
+            }
+            None => Ok(None),
+        }
+    }
+
+    fn next_value_seed<V: DeserializeSeed<'de>>(
+        &mut self,
+        seed: V,
+    ) -> Result<V::Value, Self::Error> {
+        let (key, value) = self
+            .value
+            .take()
+            .ok_or_else(|| DeserializeError::Message("value requested before key".to_string()))?;
+        seed.deserialize(Value(value)).map_err(|e| match e {
+            DeserializeError::Message(message) => {
+                DeserializeError::Message(format!("Invalid value for {}: {}", key, message))
+            }
+            e => e,
+        })
+    }
+}
+
+// a single value, converted to whatever type the visitor asks for
+struct Value<'a>(&'a str);
+
+impl Value<'_> {
+    fn parse<T: FromStr>(&self, expected: &str) -> Result<T, DeserializeError> {
+        self.0
+            .trim()
+            .parse()
+            .map_err(|_| de::Error::custom(format!("expected {}, got '{}'", expected, self.0)))
+    }
+}
+
+impl<'de> IntoDeserializer<'de, DeserializeError> for Value<'de> {
+    type Deserializer = Self;
+
+    fn into_deserializer(self) -> Self {
+        self
+    }
+}
+
+macro_rules! deserialize_from_str {
+    ($($method:ident => $visit:ident($ty:ty),)*) => {$(
+        fn $method<V: Visitor<'de>>(self, visitor: V) -> Result<V::Value, Self::Error> {
+            visitor.$visit(self.parse::<$ty>(stringify!($ty))?)
+        }
+    )*};
+}
+
+impl<'de> de::Deserializer<'de> for Value<'de> {
+    type Error = DeserializeError;
+
+    fn deserialize_any<V: Visitor<'de>>(self, visitor: V) -> Result<V::Value, Self::Error> {
+        visitor.visit_borrowed_str(self.0)
+    }
+
+    fn deserialize_bool<V: Visitor<'de>>(self, visitor: V) -> Result<V::Value, Self::Error> {
+        match parse_bool(self.0) {
+            Some(value) => visitor.visit_bool(value),
+            None => Err(de::Error::custom(format!(
+                "expected bool, got '{}'",
+                self.0
+            ))),
+        }
+    }
+
+    deserialize_from_str! {
+        deserialize_i8 => visit_i8(i8),
+        deserialize_i16 => visit_i16(i16),
+        deserialize_i32 => visit_i32(i32),
+        deserialize_i64 => visit_i64(i64),
+        deserialize_i128 => visit_i128(i128),
+        deserialize_u8 => visit_u8(u8),
+        deserialize_u16 => visit_u16(u16),
+        deserialize_u32 => visit_u32(u32),
+        deserialize_u64 => visit_u64(u64),
+        deserialize_u128 => visit_u128(u128),
+        deserialize_f32 => visit_f32(f32),
+        deserialize_f64 => visit_f64(f64),
+        deserialize_char => visit_char(char),
+    }
+
+    // a key that is present is always `Some`, even when it's empty
+    fn deserialize_option<V: Visitor<'de>>(self, visitor: V) -> Result<V::Value, Self::Error> {
+        visitor.visit_some(self)
+    }
+
+    fn deserialize_unit<V: Visitor<'de>>(self, visitor: V) -> Result<V::Value, Self::Error> {
+        visitor.visit_unit()
+    }
+
+    fn deserialize_newtype_struct<V: Visitor<'de>>(
+        self,
+        _name: &'static str,
+        visitor: V,
+    ) -> Result<V::Value, Self::Error> {
+        visitor.visit_newtype_struct(self)
+    }
+
+    // lists are comma separated, an empty value is an empty list
+    fn deserialize_seq<V: Visitor<'de>>(self, visitor: V) -> Result<V::Value, Self::Error> {
+        let items = self
+            .0
+            .split(',')
+            .map(str::trim)
+            .filter(|item| !item.is_empty())
+            .map(Value);
+        visitor.visit_seq(SeqDeserializer::new(items))
+    }
+
+    fn deserialize_tuple<V: Visitor<'de>>(
+        self,
+        _len: usize,
+        visitor: V,
+    ) -> Result<V::Value, Self::Error> {
+        self.deserialize_seq(visitor)
+    }
+
+    fn deserialize_enum<V: Visitor<'de>>(
+        self,
+        _name: &'static str,
+        _variants: &'static [&'static str],
+        visitor: V,
+    ) -> Result<V::Value, Self::Error> {
+        visitor.visit_enum(BorrowedStrDeserializer::new(self.0))
+    }
+
+    forward_to_deserialize_any! {
+        str string bytes byte_buf unit_struct tuple_struct map struct identifier ignored_any
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use serde::Deserialize;
+
+    #[derive(Debug, Deserialize, PartialEq)]
+    #[serde(rename_all = "SCREAMING_SNAKE_CASE")]
+    struct Config {
+        host: String,
+        port: u16,
+        debug: bool,
+        #[serde(rename = "ALLOWED_HOSTS")]
+        hosts: Vec<String>,
+        timeout: Option<f64>,
+        #[serde(default)]
+        level: Level,
+    }
+
+    #[derive(Debug, Default, Deserialize, PartialEq)]
+    #[serde(rename_all = "lowercase")]
+    enum Level {
+        #[default]
+        Info,
+        Debug,
+    }
+
+    #[test]
+    fn test_from_str() {
+        let content = "# service\nHOST=localhost # the host\nPORT=\"8080\"\nDEBUG=yes\n\
+                       ALLOWED_HOSTS=a.com, b.com\nLEVEL=debug\nUNUSED=1\n";
+        let config: Config = from_str(content).unwrap();
+        assert_eq!(
+            config,
+            Config {
+                host: "localhost".to_string(),
+                port: 8080,
+                debug: true,
+                hosts: vec!["a.com".to_string(), "b.com".to_string()],
+                timeout: None,
+                level: Level::Debug,
+            }
+        );
+    }
+
+    #[test]
+    fn test_last_definition_wins() {
+        let content = "HOST=a\nPORT=1\nDEBUG=0\nALLOWED_HOSTS=\nHOST=b\n";
+        let config: Config = from_str(content).unwrap();
+        assert_eq!(config.host, "b");
+        assert!(config.hosts.is_empty());
+        assert_eq!(config.level, Level::Info);
+    }
+
+    #[test]
+    fn test_invalid_value_names_key() {
+        let content = "HOST=a\nPORT=http\nDEBUG=0\nALLOWED_HOSTS=\n";
+        let error = from_str::<Config>(content).unwrap_err();
+        assert_eq!(
+            error.to_string(),
+            "Invalid value for PORT: expected u16, got 'http'"
+        );
+    }
+
+    #[test]
+    fn test_missing_key() {
+        let error = from_str::<Config>("HOST=a\n").unwrap_err();
+        assert_eq!(error.to_string(), "missing field `PORT`");
+    }
+
+    #[test]
+    fn test_parse_error() {
+        let error = from_str::<Config>("HOST=\"a\n").unwrap_err();
+        assert!(matches!(error, DeserializeError::Parse(_)));
+    }
+
+    #[test]
+    fn test_borrowed_values() {
+        #[derive(Deserialize)]
+        struct Borrowed<'a> {
+            #[serde(rename = "NAME")]
+            name: &'a str,
+        }
+        let env = EnvFile::parse("NAME=envq\n").unwrap();
+        let borrowed: Borrowed = from_env_file(&env).unwrap();
+        assert_eq!(borrowed.name, "envq");
+    }
+}
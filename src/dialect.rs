@@ -0,0 +1,380 @@
+use std::borrow::Cow;
+
+use crate::env_file::{DuplicatePolicy, KeySyntax, Quote};
+
+/// The rules of a program that loads env files.
+///
+/// Loaders disagree on quoting, escapes, comments, `export` and references,
+/// parsing with the dialect of the program that reads a file gives the values
+/// that program will see.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+#[non_exhaustive]
+pub enum Dialect {
+    /// envq's own rules, references are only resolved when asked for
+    #[default]
+    Envq,
+    /// `set -a; source .env` in bash
+    Bash,
+    /// docker compose, for `.env` and `env_file`
+    Compose,
+    /// systemd `EnvironmentFile=`
+    Systemd,
+    /// python-dotenv
+    PythonDotenv,
+    /// the `dotenv` npm package
+    Node,
+}
+
+/// Which references a dialect resolves in values.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub(crate) enum References {
+    None,
+    /// `${VAR}` and `${VAR:-default}` in the decoded value, but not `$VAR`
+    Braced,
+    /// `$VAR` and `${VAR}` with the operators of the shell
+    Shell,
+}
+
+/// What a backslash and the character after it stand for.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub(crate) enum Escape {
+    Char(char),
+    /// an escaped line break continues the value on the next line
+    LineContinuation,
+    /// not an escape, the backslash is part of the value
+    Literal,
+}
+
+impl Dialect {
+    /// The key names the program accepts.
+    pub fn key_syntax(&self) -> KeySyntax {
+        match self {
+            Dialect::Envq | Dialect::PythonDotenv => KeySyntax::Permissive,
+            Dialect::Bash | Dialect::Systemd => KeySyntax::Posix,
+            Dialect::Compose | Dialect::Node => KeySyntax::Compose,
+        }
+    }
+
+    /// Which definition of a duplicated key the program uses.
+    pub fn duplicate_policy(&self) -> DuplicatePolicy {
+        match self {
+            Dialect::Envq => DuplicatePolicy::First,
+            _ => DuplicatePolicy::Last,
+        }
+    }
+
+    /// Whether the program resolves references when it loads the file,
+    /// envq itself only does with `EnvFile::expand`.
+    pub fn interpolates(&self) -> bool {
+        matches!(
+            self,
+            Dialect::Bash | Dialect::Compose | Dialect::PythonDotenv
+        )
+    }
+
+    // `line` has its leading whitespace removed
+    pub(crate) fn is_comment(&self, line: &str) -> bool {
+        line.starts_with('#') || (*self == Dialect::Systemd && line.starts_with(';'))
+    }
+
+    pub(crate) fn allows_export(&self) -> bool {
+        *self != Dialect::Systemd
+    }
+
+    // `KEY = value` runs the command `KEY` in a shell
+    pub(crate) fn allows_spaces_around_equals(&self) -> bool {
+        *self != Dialect::Bash
+    }
+
+    // systemd only knows comment lines
+    pub(crate) fn allows_inline_comments(&self) -> bool {
+        *self != Dialect::Systemd
+    }
+
+    // the `#` that starts the inline comment of an unquoted value
+    pub(crate) fn find_inline_comment(&self, text: &str) -> Option<usize> {
+        match self {
+            Dialect::Systemd => None,
+            Dialect::Node => text.find('#'),
+            _ => find_inline_comment(text),
+        }
+    }
+
+    // the quoting of a value starting with `text`
+    pub(crate) fn quote_of(&self, text: &str) -> Quote {
+        match text.chars().next() {
+            Some('\'') => Quote::Single,
+            Some('"') => Quote::Double,
+            Some('`') if *self == Dialect::Node => Quote::Backtick,
+            _ => Quote::None,
+        }
+    }
+
+    // the position of the closing quote in the text after the opening one
+    pub(crate) fn find_closing_quote(&self, inner: &str, quote: Quote) -> Option<usize> {
+        // backslashes hide quotes everywhere but in the single quotes of a shell
+        let escapes = match quote {
+            Quote::Double | Quote::Backtick => true,
+            Quote::Single => matches!(
+                self,
+                Dialect::Compose | Dialect::PythonDotenv | Dialect::Node
+            ),
+            Quote::None => return None,
+        };
+        let close = quote.as_str().chars().next()?;
+        let mut escaped = false;
+        for (i, c) in inner.char_indices() {
+            match c {
+                _ if escaped => escaped = false,
+                '\\' if escapes => escaped = true,
+                _ if c == close => return Some(i),
+                _ => {}
+            }
+        }
+        None
+    }
+
+    pub(crate) fn escape(&self, quote: Quote, c: char) -> Escape {
+        let char = match (self, quote) {
+            // outside of quotes a shell keeps the escaped character as it is
+            (Dialect::Bash | Dialect::Systemd, Quote::None) => Some(c),
+            (Dialect::Bash | Dialect::Systemd, Quote::Double) => match c {
+                '"' | '\\' | '$' | '`' | '\n' => Some(c),
+                _ => None,
+            },
+            (Dialect::Envq, Quote::Double) => match c {
+                'n' => Some('\n'),
+                'r' => Some('\r'),
+                't' => Some('\t'),
+                '"' | '\\' | '$' => Some(c),
+                _ => None,
+            },
+            (Dialect::Compose, Quote::Single) => (c == '\'').then_some(c),
+            (Dialect::Compose, Quote::Double) => match c {
+                '"' | '\\' | '$' => Some(c),
+                _ => control_escape(c),
+            },
+            (Dialect::PythonDotenv, Quote::Single) => matches!(c, '\'' | '\\').then_some(c),
+            (Dialect::PythonDotenv, Quote::Double) => match c {
+                '\'' | '"' | '\\' => Some(c),
+                _ => control_escape(c),
+            },
+            (Dialect::Node, Quote::Double) => match c {
+                'n' => Some('\n'),
+                'r' => Some('\r'),
+                _ => None,
+            },
+            _ => None,
+        };
+        match char {
+            Some('\n') if c == '\n' => Escape::LineContinuation,
+            Some(char) => Escape::Char(char),
+            None => Escape::Literal,
+        }
+    }
+
+    // decodes the escape sequences of a value, without resolving references
+    pub(crate) fn unescape<'a>(&self, quote: Quote, text: &'a str) -> Cow<'a, str> {
+        if !text.contains('\\') {
+            return Cow::Borrowed(text);
+        }
+
+        let mut value = String::with_capacity(text.len());
+        let mut chars = text.chars().peekable();
+        while let Some(c) = chars.next() {
+            if c != '\\' {
+                value.push(c);
+                continue;
+            }
+            match chars.peek().map(|&next| self.escape(quote, next)) {
+                Some(Escape::Char(c)) => {
+                    value.push(c);
+                    chars.next();
+                }
+                Some(Escape::LineContinuation) => {
+                    chars.next();
+                }
+                Some(Escape::Literal) | None => value.push('\\'),
+            }
+        }
+        Cow::Owned(value)
+    }
+
+    pub(crate) fn references(&self, quote: Quote) -> References {
+        match (self, quote) {
+            // python-dotenv resolves references after decoding, whatever the quotes
+            (Dialect::PythonDotenv, _) => References::Braced,
+            (_, Quote::Single | Quote::Backtick) => References::None,
+            (Dialect::Envq | Dialect::Bash | Dialect::Compose, _) => References::Shell,
+            (Dialect::Systemd | Dialect::Node, _) => References::None,
+        }
+    }
+
+    // whether unescaped text would be taken for a reference
+    pub(crate) fn has_reference(&self, quote: Quote, text: &str) -> bool {
+        let references = self.references(quote);
+        let mut chars = text.chars().peekable();
+        while let Some(c) = chars.next() {
+            match c {
+                '\\' => {
+                    if let Some(&next) = chars.peek()
+                        && self.escape(quote, next) != Escape::Literal
+                    {
+                        chars.next();
+                    }
+                }
+                '$' => match references {
+                    References::None => {}
+                    References::Braced if chars.peek() != Some(&'{') => {}
+                    _ => return true,
+                },
+                _ => {}
+            }
+        }
+        false
+    }
+
+    // a shell splits unquoted values at whitespace and treats these as syntax,
+    // returns the position of the first one
+    pub(crate) fn find_shell_syntax(&self, raw: &str) -> Option<usize> {
+        if *self != Dialect::Bash {
+            return None;
+        }
+        let mut escaped = false;
+        for (i, c) in raw.char_indices() {
+            match c {
+                _ if escaped => escaped = false,
+                '\\' => escaped = true,
+                ';' | '&' | '|' | '<' | '>' | '(' | ')' | '\'' | '"' | '`' => return Some(i),
+                _ if c.is_whitespace() => return Some(i),
+                _ => {}
+            }
+        }
+        None
+    }
+
+    // an unquoted value ending with a backslash continues on the next line
+    pub(crate) fn continues_line(&self, line: &str) -> bool {
+        if !matches!(self, Dialect::Bash | Dialect::Systemd) {
+            return false;
+        }
+        let backslashes = line.len() - line.trim_end_matches('\\').len();
+        backslashes % 2 == 1
+    }
+
+    // writes `value` with `quote`, escaping what the quotes can't hold as it is
+    pub(crate) fn quote_value(&self, value: &str, quote: Quote) -> String {
+        let delimiter = quote.as_str();
+        let mut raw = String::with_capacity(value.len() + 2);
+        raw.push_str(delimiter);
+        for c in value.chars() {
+            let special = delimiter.contains(c) || matches!(c, '\\' | '$' | '`' | '\r');
+            let sequence = if c == '\r' { 'r' } else { c };
+            if quote != Quote::None && special && self.escape(quote, sequence) == Escape::Char(c) {
+                raw.push('\\');
+                raw.push(sequence);
+            } else {
+                raw.push(c);
+            }
+        }
+        raw.push_str(delimiter);
+        raw
+    }
+}
+
+// `\n`, `\t` and the other escapes of C
+fn control_escape(c: char) -> Option<char> {
+    match c {
+        'a' => Some('\x07'),
+        'b' => Some('\x08'),
+        'f' => Some('\x0c'),
+        'n' => Some('\n'),
+        'r' => Some('\r'),
+        't' => Some('\t'),
+        'v' => Some('\x0b'),
+        _ => None,
+    }
+}
+
+// a '#' only starts an inline comment when it follows whitespace,
+// so values like `abc#123` or `https://host/#frag` stay intact
+pub(crate) fn find_inline_comment(raw: &str) -> Option<usize> {
+    let mut prev = None;
+    for (i, c) in raw.char_indices() {
+        if c == '#' && prev.is_some_and(char::is_whitespace) {
+            return Some(i);
+        }
+        prev = Some(c);
+    }
+    None
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_unescape_double() {
+        let text = r#"a\nb \"c\" \$d \q"#;
+        let cases = [
+            (Dialect::Envq, "a\nb \"c\" $d \\q"),
+            (Dialect::Bash, "a\\nb \"c\" $d \\q"),
+            (Dialect::Compose, "a\nb \"c\" $d \\q"),
+            (Dialect::PythonDotenv, "a\nb \"c\" \\$d \\q"),
+            (Dialect::Node, "a\nb \\\"c\\\" \\$d \\q"),
+        ];
+        for (dialect, expected) in cases {
+            assert_eq!(
+                dialect.unescape(Quote::Double, text),
+                expected,
+                "{:?}",
+                dialect
+            );
+        }
+    }
+
+    #[test]
+    fn test_line_continuation() {
+        assert_eq!(Dialect::Systemd.unescape(Quote::None, "a\\\nb\\ c"), "ab c");
+        assert!(Dialect::Systemd.continues_line("A=x\\"));
+        assert!(!Dialect::Systemd.continues_line("A=x\\\\"));
+        assert!(!Dialect::Envq.continues_line("A=x\\"));
+    }
+
+    #[test]
+    fn test_closing_quote() {
+        assert_eq!(
+            Dialect::Compose.find_closing_quote(r"Let\'s go'", Quote::Single),
+            Some(9)
+        );
+        assert_eq!(
+            Dialect::Bash.find_closing_quote(r"a\'", Quote::Single),
+            Some(2)
+        );
+    }
+
+    #[test]
+    fn test_has_reference() {
+        assert!(Dialect::Bash.has_reference(Quote::None, "$HOME"));
+        assert!(!Dialect::Bash.has_reference(Quote::Double, "\\$HOME"));
+        assert!(!Dialect::PythonDotenv.has_reference(Quote::None, "$HOME"));
+        assert!(Dialect::PythonDotenv.has_reference(Quote::None, "${HOME}"));
+        assert!(!Dialect::Node.has_reference(Quote::None, "${HOME}"));
+    }
+
+    #[test]
+    fn test_quote_value() {
+        assert_eq!(
+            Dialect::Envq.quote_value("a\"$b\r", Quote::Double),
+            "\"a\\\"\\$b\\r\""
+        );
+        assert_eq!(
+            Dialect::Bash.quote_value("`id`", Quote::Double),
+            "\"\\`id\\`\""
+        );
+        assert_eq!(
+            Dialect::PythonDotenv.quote_value("it's", Quote::Single),
+            "'it\\'s'"
+        );
+    }
+}
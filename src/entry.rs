@@ -0,0 +1,287 @@
+use crate::dialect::Dialect;
+use crate::env_file::{Entry, EnvFile};
+use crate::error::KeyError;
+
+/// A key of an [`EnvFile`], as returned by [`EnvFile::iter`].
+#[derive(Debug, Clone, Copy)]
+pub struct KeyRef<'a> {
+    index: usize,
+    entry: &'a Entry,
+}
+
+impl<'a> KeyRef<'a> {
+    pub(crate) fn new(index: usize, entry: &'a Entry) -> Self {
+        KeyRef { index, entry }
+    }
+
+    pub fn key(&self) -> &'a str {
+        self.entry.key().unwrap_or_default()
+    }
+
+    pub fn value(&self) -> &'a str {
+        self.entry.value().unwrap_or_default()
+    }
+
+    pub fn comment(&self) -> Option<&'a str> {
+        self.entry.comment()
+    }
+
+    /// Position of the key in [`EnvFile::entries`].
+    pub fn index(&self) -> usize {
+        self.index
+    }
+
+    pub fn entry(&self) -> &'a Entry {
+        self.entry
+    }
+}
+
+/// A key of an [`EnvFile`] that can be edited in place.
+#[derive(Debug)]
+pub struct KeyMut<'a> {
+    index: usize,
+    entry: &'a mut Entry,
+    // new values are written the way the file's loader reads them
+    dialect: Dialect,
+}
+
+impl<'a> KeyMut<'a> {
+    pub(crate) fn new(index: usize, entry: &'a mut Entry, dialect: Dialect) -> Self {
+        KeyMut {
+            index,
+            entry,
+            dialect,
+        }
+    }
+
+    pub fn key(&self) -> &str {
+        self.entry.key().unwrap_or_default()
+    }
+
+    pub fn value(&self) -> &str {
+        self.entry.value().unwrap_or_default()
+    }
+
+    pub fn comment(&self) -> Option<&str> {
+        self.entry.comment()
+    }
+
+    /// Position of the key in [`EnvFile::entries`].
+    pub fn index(&self) -> usize {
+        self.index
+    }
+
+    /// Replaces the value, keeping its quoting when the new value allows it.
+    /// Fails when the dialect of the file has no quoting that reads back as
+    /// the value.
+    pub fn set_value(&mut self, value: &str) -> Result<(), KeyError> {
+        self.entry.set_value(value, self.dialect)
+    }
+
+    pub fn set_comment(&mut self, comment: &str) {
+        self.entry.set_comment(comment);
+    }
+
+    pub fn delete_comment(&mut self) {
+        self.entry.delete_comment();
+    }
+}
+
+/// The first definition of a key in an [`EnvFile`], or the place to add it.
+///
+/// Returned by [`EnvFile::entry`].
+#[derive(Debug)]
+pub enum KeyEntry<'a> {
+    Occupied(OccupiedKey<'a>),
+    Vacant(VacantKey<'a>),
+}
+
+impl<'a> KeyEntry<'a> {
+    pub fn key(&self) -> &str {
+        match self {
+            KeyEntry::Occupied(entry) => entry.key(),
+            KeyEntry::Vacant(entry) => entry.key(),
+        }
+    }
+
+    /// Appends the key with `value` if it isn't defined yet, see [`VacantKey::insert`].
+    pub fn or_insert(self, value: &str) -> Result<KeyMut<'a>, KeyError> {
+        match self {
+            KeyEntry::Occupied(entry) => Ok(entry.into_mut()),
+            KeyEntry::Vacant(entry) => entry.insert(value),
+        }
+    }
+
+    /// Like [`KeyEntry::or_insert`], computing the value only when needed.
+    pub fn or_insert_with(self, value: impl FnOnce() -> String) -> Result<KeyMut<'a>, KeyError> {
+        match self {
+            KeyEntry::Occupied(entry) => Ok(entry.into_mut()),
+            KeyEntry::Vacant(entry) => entry.insert(&value()),
+        }
+    }
+
+    /// Edits the key if it is defined.
+    pub fn and_modify(self, f: impl FnOnce(KeyMut<'_>)) -> Self {
+        match self {
+            KeyEntry::Occupied(mut entry) => {
+                f(entry.get_mut());
+                KeyEntry::Occupied(entry)
+            }
+            KeyEntry::Vacant(entry) => KeyEntry::Vacant(entry),
+        }
+    }
+}
+
+#[derive(Debug)]
+pub struct OccupiedKey<'a> {
+    file: &'a mut EnvFile,
+    index: usize,
+}
+
+impl<'a> OccupiedKey<'a> {
+    pub(crate) fn new(file: &'a mut EnvFile, index: usize) -> Self {
+        OccupiedKey { file, index }
+    }
+
+    pub fn key(&self) -> &str {
+        self.get().key()
+    }
+
+    pub fn value(&self) -> &str {
+        self.get().value()
+    }
+
+    /// Position of the key in [`EnvFile::entries`].
+    pub fn index(&self) -> usize {
+        self.index
+    }
+
+    pub fn get(&self) -> KeyRef<'_> {
+        KeyRef::new(self.index, &self.file.entries()[self.index])
+    }
+
+    pub fn get_mut(&mut self) -> KeyMut<'_> {
+        let dialect = self.file.dialect();
+        KeyMut::new(self.index, self.file.entry_mut(self.index), dialect)
+    }
+
+    pub fn into_mut(self) -> KeyMut<'a> {
+        let dialect = self.file.dialect();
+        KeyMut::new(self.index, self.file.entry_mut(self.index), dialect)
+    }
+
+    /// Moves the line to `index` in [`EnvFile::entries`], or to the end if
+    /// `index` is past it.
+    pub fn move_to(&mut self, index: usize) {
+        let entry = self.file.remove_entry(self.index);
+        self.index = index.min(self.file.entries().len());
+        self.file.insert_entry(self.index, entry);
+    }
+
+    /// Removes the line defining the key, including its comment.
+    pub fn remove(self) -> Entry {
+        self.file.remove_entry(self.index)
+    }
+}
+
+#[derive(Debug)]
+pub struct VacantKey<'a> {
+    file: &'a mut EnvFile,
+    key: String,
+}
+
+impl<'a> VacantKey<'a> {
+    pub(crate) fn new(file: &'a mut EnvFile, key: &str) -> Self {
+        VacantKey {
+            file,
+            key: key.to_string(),
+        }
+    }
+
+    pub fn key(&self) -> &str {
+        &self.key
+    }
+
+    /// Appends the key at the end of the file. Fails for key names the file's
+    /// [`KeySyntax`](crate::KeySyntax) doesn't accept.
+    pub fn insert(self, value: &str) -> Result<KeyMut<'a>, KeyError> {
+        let index = self.file.entries().len();
+        self.insert_at(index, value)
+    }
+
+    /// Inserts the key at `index` in [`EnvFile::entries`], or at the end if
+    /// `index` is past it.
+    pub fn insert_at(self, index: usize, value: &str) -> Result<KeyMut<'a>, KeyError> {
+        self.file.check_key(&self.key)?;
+        let index = index.min(self.file.entries().len());
+        let dialect = self.file.dialect();
+        let entry = Entry::new_key_value(&self.key, value, dialect)?;
+        self.file.insert_entry(index, entry);
+        Ok(KeyMut::new(index, self.file.entry_mut(index), dialect))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_or_insert_appends_missing_key() {
+        let mut env = EnvFile::parse("A=1\n").unwrap();
+        let entry = env.entry("B").or_insert("2").unwrap();
+        assert_eq!(entry.index(), 1);
+        assert_eq!(env.to_string(), "A=1\nB=2\n");
+    }
+
+    #[test]
+    fn test_or_insert_keeps_existing_value() {
+        let mut env = EnvFile::parse("A=1\n").unwrap();
+        assert_eq!(env.entry("A").or_insert("2").unwrap().value(), "1");
+        assert_eq!(env.to_string(), "A=1\n");
+    }
+
+    #[test]
+    fn test_and_modify() {
+        let mut env = EnvFile::parse("A='1' # one\n").unwrap();
+        env.entry("A").and_modify(|mut a| a.set_value("2").unwrap());
+        env.entry("B").and_modify(|mut b| b.set_value("2").unwrap());
+        assert_eq!(env.to_string(), "A='2' # one\n");
+    }
+
+    #[test]
+    fn test_entry_uses_first_definition() {
+        let mut env = EnvFile::parse("A=1\nB=2\nA=3\n").unwrap();
+        match env.entry("A") {
+            KeyEntry::Occupied(entry) => assert_eq!(entry.index(), 0),
+            KeyEntry::Vacant(_) => panic!("A is defined"),
+        }
+    }
+
+    #[test]
+    fn test_move_to() {
+        let mut env = EnvFile::parse("# header\n\nA=1\nB=2 # two\n").unwrap();
+        if let KeyEntry::Occupied(mut entry) = env.entry("B") {
+            entry.move_to(2);
+            assert_eq!(entry.index(), 2);
+        }
+        assert_eq!(env.to_string(), "# header\n\nB=2 # two\nA=1\n");
+    }
+
+    #[test]
+    fn test_remove() {
+        let mut env = EnvFile::parse("A=1\nB=2\n").unwrap();
+        if let KeyEntry::Occupied(entry) = env.entry("A") {
+            assert_eq!(entry.remove().value(), Some("1"));
+        }
+        assert_eq!(env.to_string(), "B=2\n");
+    }
+
+    #[test]
+    fn test_insert_at() {
+        let mut env = EnvFile::parse("A=1\nC=3\n").unwrap();
+        if let KeyEntry::Vacant(entry) = env.entry("B") {
+            entry.insert_at(1, "2").unwrap().set_comment("two");
+        }
+        assert_eq!(env.to_string(), "A=1\nB=2 # two\nC=3\n");
+    }
+}
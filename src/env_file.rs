@@ -1,59 +1,325 @@
-use anyhow::Result;
+use std::borrow::Cow;
+use std::collections::{HashMap, HashSet};
 use std::fmt;
 
+use crate::dialect::{Dialect, find_inline_comment};
+use crate::entry::{KeyEntry, KeyMut, KeyRef, OccupiedKey, VacantKey};
+use crate::error::{KeyError, ParseError, ParseErrorKind};
+use crate::expand::is_name;
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+#[non_exhaustive]
+pub enum Quote {
+    #[default]
+    None,
+    Single,
+    Double,
+    /// only the node dialect quotes with backticks
+    Backtick,
+}
+
+impl Quote {
+    pub(crate) fn as_str(&self) -> &'static str {
+        match self {
+            Quote::None => "",
+            Quote::Single => "'",
+            Quote::Double => "\"",
+            Quote::Backtick => "`",
+        }
+    }
+}
+
+/// The whitespace and separators around the parts of a `KEY=value` line,
+/// recorded so that untouched lines are written back byte-for-byte.
+#[derive(Debug, Clone, PartialEq)]
+#[non_exhaustive]
+pub struct Trivia {
+    /// before the key (or `export`)
+    pub indent: String,
+    /// between `export` and the key
+    pub export_gap: String,
+    /// between the key and `=`
+    pub before_equals: String,
+    /// between `=` and the value
+    pub after_equals: String,
+    /// between the value and the `#` of the inline comment
+    pub before_comment: String,
+    /// between the `#` and the text of the inline comment
+    pub comment_gap: String,
+    /// whatever follows the value or comment until the end of the line
+    pub trailing: String,
+}
+
+impl Default for Trivia {
+    fn default() -> Self {
+        Trivia {
+            indent: String::new(),
+            export_gap: " ".to_string(),
+            before_equals: String::new(),
+            after_equals: String::new(),
+            before_comment: " ".to_string(),
+            comment_gap: " ".to_string(),
+            trailing: String::new(),
+        }
+    }
+}
+
 #[derive(Debug, Clone, PartialEq)]
+#[non_exhaustive]
 pub enum Entry {
+    #[non_exhaustive]
     KeyValue {
+        export: bool,
         key: String,
         value: String,
+        quote: Quote,
+        /// the value as written in the file, including quotes and escapes
+        raw: String,
         comment: Option<String>,
+        trivia: Box<Trivia>,
     },
+    /// a full comment line, as written in the file
     Comment(String),
-    Blank,
+    /// an empty line, keeping any whitespace it contains
+    Blank(String),
+    /// a line that failed to parse in lenient mode, kept verbatim
+    Invalid(String),
+}
+
+/// Which entry wins when a key is defined more than once.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub enum DuplicatePolicy {
+    /// the first definition, what envq has always used
+    #[default]
+    First,
+    /// the last definition, what dotenv loaders use
+    Last,
 }
 
+/// Where `EnvFile::set_value_at` adds a key that isn't defined yet.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub enum Position<'a> {
+    /// at the end of the file
+    #[default]
+    End,
+    /// right after the first definition of a key
+    After(&'a str),
+    /// right before the first definition of a key and the comment lines above it
+    Before(&'a str),
+    /// after the last key of the section introduced by a comment like `# Database`,
+    /// a section ends at the next blank line after its first key
+    Section(&'a str),
+}
+
+/// A parsed env file that can be queried, edited and written back.
 #[derive(Debug)]
 pub struct EnvFile {
-    header: Vec<String>,
     entries: Vec<Entry>,
+    // positions in `entries` of every definition of a key, in file order
+    index: HashMap<String, Vec<usize>>,
+    serialize_options: SerializeOptions,
+    key_syntax: KeySyntax,
+    dialect: Dialect,
+}
+
+#[derive(Debug, Clone, Default)]
+#[non_exhaustive]
+pub struct ParseOptions {
+    /// keep invalid lines as `Entry::Invalid` instead of failing
+    pub lenient: bool,
+    /// which key names are accepted, other keys are parse errors;
+    /// `None` accepts the keys of the dialect
+    pub key_syntax: Option<KeySyntax>,
+    /// the loader whose rules the file is read with
+    pub dialect: Dialect,
+}
+
+/// The key names a loader accepts.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+#[non_exhaustive]
+pub enum KeySyntax {
+    /// letters, digits and `_`, not starting with a digit, what a shell can `source`
+    Posix,
+    /// like `Posix`, with `.` and `-` allowed after the first character, as docker compose does
+    Compose,
+    /// anything without whitespace, control characters or `=` that doesn't
+    /// start like a comment
+    #[default]
+    Permissive,
+}
+
+impl KeySyntax {
+    pub fn is_valid(&self, key: &str) -> bool {
+        let mut chars = key.chars();
+        let Some(first) = chars.next() else {
+            return false;
+        };
+        match self {
+            KeySyntax::Posix => is_name(key),
+            KeySyntax::Compose => {
+                (first == '_' || first.is_ascii_alphabetic())
+                    && chars.all(|c| matches!(c, '_' | '.' | '-') || c.is_ascii_alphanumeric())
+            }
+            KeySyntax::Permissive => {
+                first != '#'
+                    && !key
+                        .chars()
+                        .any(|c| c == '=' || c.is_whitespace() || c.is_control())
+            }
+        }
+    }
+}
+
+/// How an `EnvFile` is written, detected from the parsed content.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+#[non_exhaustive]
+pub struct SerializeOptions {
+    pub line_ending: LineEnding,
+    /// start with a UTF-8 byte order mark
+    pub bom: bool,
+    /// end the last line with a line ending
+    pub final_newline: bool,
+}
+
+impl Default for SerializeOptions {
+    fn default() -> Self {
+        SerializeOptions {
+            line_ending: LineEnding::Lf,
+            bom: false,
+            final_newline: true,
+        }
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub enum LineEnding {
+    #[default]
+    Lf,
+    CrLf,
+}
+
+impl LineEnding {
+    fn as_str(&self) -> &'static str {
+        match self {
+            LineEnding::Lf => "\n",
+            LineEnding::CrLf => "\r\n",
+        }
+    }
 }
 
 impl EnvFile {
-    pub fn parse(content: &str) -> Result<Self> {
-        let mut header = Vec::new();
+    pub fn parse(content: &str) -> Result<Self, ParseError> {
+        Self::parse_with(content, &ParseOptions::default())
+    }
+
+    /// Parses with the given options, in lenient mode errors are dropped.
+    pub fn parse_with(content: &str, options: &ParseOptions) -> Result<Self, ParseError> {
+        let (env_file, mut errors) = Self::parse_internal(content, options);
+        match errors.pop() {
+            Some(error) if !options.lenient => Err(error),
+            _ => Ok(env_file),
+        }
+    }
+
+    /// Parses as much as possible, keeping invalid lines as `Entry::Invalid`
+    /// and returning every error instead of stopping at the first one.
+    pub fn parse_lenient(content: &str) -> (Self, Vec<ParseError>) {
+        Self::parse_lenient_with(content, &ParseOptions::default())
+    }
+
+    /// Like `parse_lenient`, with the other options taken from `options`.
+    pub fn parse_lenient_with(content: &str, options: &ParseOptions) -> (Self, Vec<ParseError>) {
+        let options = ParseOptions {
+            lenient: true,
+            ..options.clone()
+        };
+        Self::parse_internal(content, &options)
+    }
+
+    fn parse_internal(content: &str, options: &ParseOptions) -> (Self, Vec<ParseError>) {
+        let lenient = options.lenient;
+        let dialect = options.dialect;
+        let key_syntax = options.key_syntax.unwrap_or(dialect.key_syntax());
+        // some editors start the file with a byte order mark
+        let (bom, content) = match content.strip_prefix('\u{feff}') {
+            Some(content) => (true, content),
+            None => (false, content),
+        };
+        // the first line break decides how the file gets written back
+        let line_ending = match content.find('\n') {
+            Some(pos) if content[..pos].ends_with('\r') => LineEnding::CrLf,
+            _ => LineEnding::Lf,
+        };
+        let final_newline = content.is_empty() || content.ends_with('\n');
+
         let mut entries = Vec::new();
+        let mut errors = Vec::new();
         let mut found_first_key = false;
+        let mut line_number = 0;
 
-        for line in content.lines() {
-            let trimmed = line.trim();
+        let mut lines = content.lines();
+        while let Some(line) = lines.next() {
+            line_number += 1;
+            let start = line_number;
 
-            if !found_first_key {
-                // before first key, everything goes to header
-                if trimmed.is_empty() {
-                    // blank lines before first key are allowed
+            // look ahead on a copy, so an unterminated quote only costs its own line
+            let mut rest = lines.clone();
+            let line = match join_multiline(line, &mut rest, start, dialect) {
+                Ok(line) => {
+                    lines = rest;
+                    line
+                }
+                Err(e) => {
+                    errors.push(e);
+                    if !lenient {
+                        break;
+                    }
+                    entries.push(Entry::Invalid(line.to_string()));
                     continue;
-                } else if trimmed.starts_with('#') {
-                    // strip the # and any following space
-                    let content = trimmed.strip_prefix('#').unwrap_or(trimmed);
-                    let content = content.strip_prefix(' ').unwrap_or(content);
-                    header.push(content.to_string());
-                } else if parse_key_value(line).is_some() {
-                    // found first key, so it's an entry
-                    found_first_key = true;
-                    entries.push(parse_line(line)?);
-                } else {
-                    return Err(anyhow::anyhow!(
-                        "Invalid line before first key (must be comment or blank): {}",
-                        line
-                    ));
                 }
-            } else {
-                // after first key, parse normally
-                entries.push(parse_line(line)?);
+            };
+            let line = line.as_ref();
+            line_number += line.matches('\n').count();
+
+            let entry = match parse_line(line, start, dialect, key_syntax) {
+                // before first key, only comments (the header) and blank lines are allowed
+                Err(e) if !found_first_key && e.kind == ParseErrorKind::InvalidLine => {
+                    Err(ParseError {
+                        kind: ParseErrorKind::InvalidLineBeforeFirstKey,
+                        ..e
+                    })
+                }
+                result => result,
+            };
+
+            match entry {
+                Ok(entry) => {
+                    found_first_key |= matches!(entry, Entry::KeyValue { .. });
+                    entries.push(entry);
+                }
+                Err(e) => {
+                    errors.push(e);
+                    if !lenient {
+                        break;
+                    }
+                    entries.push(Entry::Invalid(line.to_string()));
+                }
             }
         }
 
-        Ok(EnvFile { header, entries })
+        let mut env_file = EnvFile {
+            entries,
+            index: HashMap::new(),
+            serialize_options: SerializeOptions {
+                line_ending,
+                bom,
+                final_newline,
+            },
+            key_syntax,
+            dialect,
+        };
+        env_file.reindex();
+        (env_file, errors)
     }
 
     pub fn list_keys(&self) -> Vec<&str> {
@@ -66,300 +332,1133 @@ impl EnvFile {
             .collect()
     }
 
+    pub fn list_values(&self) -> Vec<(&str, &str)> {
+        self.entries
+            .iter()
+            .filter_map(|entry| match entry {
+                Entry::KeyValue { key, value, .. } => Some((key.as_str(), value.as_str())),
+                _ => None,
+            })
+            .collect()
+    }
+
     pub fn get_value(&self, key: &str) -> Option<&str> {
-        self.entries.iter().find_map(|entry| match entry {
-            Entry::KeyValue { key: k, value, .. } if k == key => Some(value.as_str()),
+        self.get_value_with(key, DuplicatePolicy::First)
+    }
+
+    pub fn get_value_with(&self, key: &str, policy: DuplicatePolicy) -> Option<&str> {
+        match self.find(key, policy)? {
+            Entry::KeyValue { value, .. } => Some(value.as_str()),
             _ => None,
-        })
+        }
     }
 
     pub fn get_comment(&self, key: &str) -> Option<&str> {
-        self.entries.iter().find_map(|entry| match entry {
-            Entry::KeyValue {
-                key: k, comment, ..
-            } if k == key => comment.as_deref(),
+        match self.find(key, DuplicatePolicy::First)? {
+            Entry::KeyValue { comment, .. } => comment.as_deref(),
             _ => None,
-        })
+        }
     }
 
-    pub fn get_header(&self) -> Option<String> {
-        if self.header.is_empty() {
-            None
-        } else {
-            Some(self.header.join("\n") + "\n")
+    /// Returns every key defined more than once, with the line numbers of its definitions.
+    pub fn duplicates(&self) -> Vec<(&str, Vec<usize>)> {
+        let mut duplicates: Vec<(&str, Vec<usize>)> = Vec::new();
+        let mut positions = HashMap::new();
+        for (key, line) in self.key_lines() {
+            if self.index[key].len() < 2 {
+                continue;
+            }
+            let pos = *positions.entry(key).or_insert_with(|| {
+                duplicates.push((key, Vec::new()));
+                duplicates.len() - 1
+            });
+            duplicates[pos].1.push(line);
         }
+        duplicates
     }
 
-    pub fn set_value(&mut self, key: &str, value: &str) {
-        // find existing key and update it, preserving comment
-        for entry in &mut self.entries {
-            if let Entry::KeyValue {
-                key: k, value: v, ..
-            } = entry
-                && k == key
+    /// Removes all but the winning definition of every duplicated key.
+    pub fn dedupe(&mut self, policy: DuplicatePolicy) {
+        let mut keep = vec![true; self.entries.len()];
+        let mut seen = HashSet::new();
+
+        let indices: Vec<usize> = match policy {
+            DuplicatePolicy::First => (0..self.entries.len()).collect(),
+            DuplicatePolicy::Last => (0..self.entries.len()).rev().collect(),
+        };
+        for index in indices {
+            if let Entry::KeyValue { key, .. } = &self.entries[index]
+                && !seen.insert(key.as_str())
             {
-                *v = value.to_string();
-                return;
+                keep[index] = false;
             }
         }
 
-        // key not found, add new entry
-        self.entries.push(Entry::KeyValue {
-            key: key.to_string(),
-            value: value.to_string(),
-            comment: None,
-        });
+        let mut keep = keep.into_iter();
+        self.entries.retain(|_| keep.next().unwrap_or(true));
+        self.reindex();
     }
 
-    pub fn set_comment(&mut self, key: &str, comment: &str) {
-        for entry in &mut self.entries {
-            if let Entry::KeyValue {
-                key: k, comment: c, ..
-            } = entry
-                && k == key
-            {
-                *c = Some(comment.to_string());
-                return;
-            }
+    pub fn get_header(&self) -> Option<String> {
+        // the header is made of the comment lines before the first key
+        let header: Vec<&str> = self.entries[..self.header_len()]
+            .iter()
+            .filter_map(|entry| match entry {
+                Entry::Comment(line) => {
+                    // strip the # and any following space
+                    let content = line.trim();
+                    let content = content.strip_prefix('#').unwrap_or(content);
+                    Some(content.strip_prefix(' ').unwrap_or(content))
+                }
+                _ => None,
+            })
+            .collect();
+
+        if header.is_empty() {
+            None
+        } else {
+            Some(header.join("\n") + "\n")
         }
     }
 
-    pub fn set_header(&mut self, header: &str) {
-        self.header = header.lines().map(|s| s.to_string()).collect();
+    /// Updates an existing key, preserving comment and quoting, or appends a
+    /// new one. Fails for new key names the file's `KeySyntax` doesn't accept.
+    pub fn set_value(&mut self, key: &str, value: &str) -> Result<(), KeyError> {
+        self.set_value_at(key, value, Position::End)
     }
 
-    pub fn delete_key(&mut self, key: &str) {
-        self.entries.retain(|entry| match entry {
-            Entry::KeyValue { key: k, .. } => k != key,
-            _ => true,
-        });
+    /// Like `set_value`, but a new key is added at `position` instead of the end.
+    /// Existing keys keep their place, and the key `position` refers to only
+    /// has to exist for a new key.
+    pub fn set_value_at(
+        &mut self,
+        key: &str,
+        value: &str,
+        position: Position,
+    ) -> Result<(), KeyError> {
+        if let KeyEntry::Occupied(mut entry) = self.entry(key) {
+            return entry.get_mut().set_value(value);
+        }
+        // the anchor only places a new key, so it only has to exist then
+        let index = self.position_index(position)?;
+        VacantKey::new(self, key).insert_at(index, value)?;
+        Ok(())
     }
 
-    pub fn delete_comment(&mut self, key: &str) {
+    pub fn set_comment(&mut self, key: &str, comment: &str) {
+        if let KeyEntry::Occupied(mut entry) = self.entry(key) {
+            entry.get_mut().set_comment(comment);
+        }
+    }
+
+    pub fn set_export(&mut self, export: bool) {
         for entry in &mut self.entries {
-            if let Entry::KeyValue {
-                key: k, comment, ..
-            } = entry
-                && k == key
-            {
-                *comment = None;
-                return;
+            if let Entry::KeyValue { export: e, .. } = entry {
+                *e = export;
             }
         }
     }
 
-    pub fn delete_header(&mut self) {
-        self.header.clear();
+    pub fn set_header(&mut self, header: &str) {
+        let mut lines: Vec<Entry> = header
+            .lines()
+            .map(|line| {
+                if line.is_empty() {
+                    Entry::Comment("#".to_string())
+                } else {
+                    Entry::Comment(format!("# {}", line))
+                }
+            })
+            .collect();
+
+        if !lines.is_empty() {
+            lines.push(Entry::Blank(String::new()));
+        }
+
+        let header_len = self.header_len();
+        self.entries.splice(..header_len, lines);
+        self.reindex();
     }
-}
 
-impl fmt::Display for EnvFile {
-    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
-        // add header if present
-        if !self.header.is_empty() {
-            for line in &self.header {
-                writeln!(f, "# {}", line)?;
-            }
-            writeln!(f)?;
+    pub fn delete_key(&mut self, key: &str) {
+        if self.index.remove(key).is_some() {
+            self.entries.retain(|entry| entry.key() != Some(key));
+            self.reindex();
         }
+    }
 
-        // add entries
-        for entry in &self.entries {
-            match entry {
-                Entry::KeyValue {
-                    key,
-                    value,
-                    comment,
-                } => {
-                    write!(f, "{}={}", key, value)?;
-                    if let Some(c) = comment {
-                        write!(f, " # {}", c)?;
-                    }
-                    writeln!(f)?;
-                }
-                Entry::Comment(c) => {
-                    writeln!(f, "{}", c)?;
-                }
-                Entry::Blank => {
-                    writeln!(f)?;
-                }
+    /// Renames every definition of `old` in place, keeping values and comments.
+    /// With `force`, existing definitions of `new` are deleted instead of failing.
+    pub fn rename_key(&mut self, old: &str, new: &str, force: bool) -> Result<(), KeyError> {
+        if !self.index.contains_key(old) {
+            return Err(KeyError::NotFound(old.to_string()));
+        }
+        if old == new {
+            return Ok(());
+        }
+        self.check_key(new)?;
+        if self.index.contains_key(new) {
+            if !force {
+                return Err(KeyError::AlreadyExists(new.to_string()));
             }
+            self.delete_key(new);
         }
 
+        let positions = self.index.remove(old).unwrap_or_default();
+        for &index in &positions {
+            self.entries[index].set_key(new);
+        }
+        self.index.insert(new.to_string(), positions);
         Ok(())
     }
-}
 
-fn parse_line(line: &str) -> Result<Entry> {
-    let trimmed = line.trim();
-
-    if trimmed.is_empty() {
-        return Ok(Entry::Blank);
+    pub fn delete_comment(&mut self, key: &str) {
+        if let KeyEntry::Occupied(mut entry) = self.entry(key) {
+            entry.get_mut().delete_comment();
+        }
     }
 
-    if trimmed.starts_with('#') {
-        return Ok(Entry::Comment(line.to_string()));
+    pub fn delete_header(&mut self) {
+        let header_len = self.header_len();
+        self.entries.drain(..header_len);
+        self.reindex();
     }
 
-    if let Some((key, value, comment)) = parse_key_value(line) {
-        return Ok(Entry::KeyValue {
-            key: key.to_string(),
-            value: value.to_string(),
-            comment: comment.map(|s| s.to_string()),
-        });
+    pub fn serialize_options(&self) -> &SerializeOptions {
+        &self.serialize_options
     }
 
-    Err(anyhow::anyhow!(
-        "Invalid line (must be KEY=VALUE, comment, or blank): {}",
-        line
-    ))
-}
+    pub fn set_serialize_options(&mut self, options: SerializeOptions) {
+        self.serialize_options = options;
+    }
 
-fn parse_key_value(line: &str) -> Option<(&str, &str, Option<&str>)> {
-    // find the first '=' sign
-    let equal_pos = line.find('=')?;
-    let key = line[..equal_pos].trim();
+    /// The key names accepted when parsing and by `set_value_at` and `rename_key`.
+    pub fn key_syntax(&self) -> KeySyntax {
+        self.key_syntax
+    }
 
-    // key must not be empty
-    if key.is_empty() {
-        return None;
+    pub fn set_key_syntax(&mut self, key_syntax: KeySyntax) {
+        self.key_syntax = key_syntax;
     }
 
-    let rest = &line[equal_pos + 1..];
+    /// The loader whose rules the file was parsed with, new values are
+    /// written so that it reads them back unchanged.
+    pub fn dialect(&self) -> Dialect {
+        self.dialect
+    }
 
-    // look for comment after value
-    if let Some(hash_pos) = rest.find('#') {
-        let value = rest[..hash_pos].trim();
-        let comment = rest[hash_pos + 1..].trim();
-        Some((
-            key,
-            value,
-            if comment.is_empty() {
-                None
-            } else {
-                Some(comment)
-            },
-        ))
-    } else {
-        let value = rest.trim();
-        Some((key, value, None))
+    /// All lines of the file, including comments and blank lines.
+    pub fn entries(&self) -> &[Entry] {
+        &self.entries
     }
-}
 
-#[cfg(test)]
-mod tests {
-    use super::*;
+    /// Iterates over the keys in file order.
+    pub fn iter(&self) -> impl Iterator<Item = KeyRef<'_>> {
+        self.entries
+            .iter()
+            .enumerate()
+            .filter(|(_, entry)| entry.key().is_some())
+            .map(|(index, entry)| KeyRef::new(index, entry))
+    }
 
-    #[test]
-    fn test_parse_simple() {
-        let content = "KEY=value\n";
-        let env = EnvFile::parse(content).unwrap();
-        assert_eq!(env.list_keys(), vec!["KEY"]);
-        assert_eq!(env.get_value("KEY"), Some("value"));
+    /// Iterates over the keys in file order, allowing to edit them.
+    pub fn iter_mut(&mut self) -> impl Iterator<Item = KeyMut<'_>> {
+        let dialect = self.dialect;
+        self.entries
+            .iter_mut()
+            .enumerate()
+            .filter(|(_, entry)| entry.key().is_some())
+            .map(move |(index, entry)| KeyMut::new(index, entry, dialect))
     }
 
-    #[test]
-    fn test_parse_with_comment() {
-        let content = "KEY=value # this is a comment\n";
-        let env = EnvFile::parse(content).unwrap();
-        assert_eq!(env.get_value("KEY"), Some("value"));
-        assert_eq!(env.get_comment("KEY"), Some("this is a comment"));
+    /// Gets the first definition of `key` for in-place editing, or a slot to insert it.
+    pub fn entry(&mut self, key: &str) -> KeyEntry<'_> {
+        match self.index.get(key).and_then(|positions| positions.first()) {
+            Some(&index) => KeyEntry::Occupied(OccupiedKey::new(self, index)),
+            None => KeyEntry::Vacant(VacantKey::new(self, key)),
+        }
     }
 
-    #[test]
-    fn test_parse_with_header() {
-        let content = "# Header line 1\n# Header line 2\n\nKEY=value\n";
-        let env = EnvFile::parse(content).unwrap();
-        assert_eq!(
-            env.get_header(),
-            Some("Header line 1\nHeader line 2\n".to_string())
-        );
-        assert_eq!(env.get_value("KEY"), Some("value"));
+    pub(crate) fn entry_mut(&mut self, index: usize) -> &mut Entry {
+        &mut self.entries[index]
     }
 
-    #[test]
-    fn test_set_value_preserves_comment() {
-        let content = "KEY=old # comment\n";
-        let mut env = EnvFile::parse(content).unwrap();
-        env.set_value("KEY", "new");
-        assert_eq!(env.get_value("KEY"), Some("new"));
-        assert_eq!(env.get_comment("KEY"), Some("comment"));
+    pub(crate) fn insert_entry(&mut self, index: usize, entry: Entry) {
+        if index == self.entries.len() {
+            // appending doesn't move other entries, so the index only grows
+            if let Some(key) = entry.key() {
+                self.index.entry(key.to_string()).or_default().push(index);
+            }
+            self.entries.push(entry);
+        } else {
+            self.entries.insert(index, entry);
+            self.reindex();
+        }
     }
 
-    #[test]
-    fn test_set_value_new_key() {
-        let content = "KEY1=value1\n";
-        let mut env = EnvFile::parse(content).unwrap();
-        env.set_value("KEY2", "value2");
-        assert_eq!(env.list_keys(), vec!["KEY1", "KEY2"]);
-        assert_eq!(env.get_value("KEY2"), Some("value2"));
+    pub(crate) fn set_entries(&mut self, entries: Vec<Entry>) {
+        self.entries = entries;
+        self.reindex();
     }
 
-    #[test]
-    fn test_delete_key_removes_comment() {
-        let content = "KEY=value # comment\n";
-        let mut env = EnvFile::parse(content).unwrap();
-        env.delete_key("KEY");
-        assert_eq!(env.list_keys().len(), 0);
-        assert_eq!(env.get_comment("KEY"), None);
+    pub(crate) fn remove_entry(&mut self, index: usize) -> Entry {
+        let entry = self.entries.remove(index);
+        self.reindex();
+        entry
     }
 
-    #[test]
-    fn test_delete_comment_only() {
-        let content = "KEY=value # comment\n";
-        let mut env = EnvFile::parse(content).unwrap();
-        env.delete_comment("KEY");
-        assert_eq!(env.get_value("KEY"), Some("value"));
-        assert_eq!(env.get_comment("KEY"), None);
+    fn reindex(&mut self) {
+        self.index.clear();
+        for (index, entry) in self.entries.iter().enumerate() {
+            if let Some(key) = entry.key() {
+                self.index.entry(key.to_string()).or_default().push(index);
+            }
+        }
     }
 
-    #[test]
-    fn test_set_comment() {
-        let content = "KEY=value\n";
-        let mut env = EnvFile::parse(content).unwrap();
-        env.set_comment("KEY", "new comment");
-        assert_eq!(env.get_comment("KEY"), Some("new comment"));
+    fn find(&self, key: &str, policy: DuplicatePolicy) -> Option<&Entry> {
+        let positions = self.index.get(key)?;
+        let index = match policy {
+            DuplicatePolicy::First => positions.first()?,
+            DuplicatePolicy::Last => positions.last()?,
+        };
+        Some(&self.entries[*index])
     }
 
-    #[test]
-    fn test_set_header() {
-        let content = "KEY=value\n";
-        let mut env = EnvFile::parse(content).unwrap();
-        env.set_header("New header\nLine 2");
-        assert_eq!(env.get_header(), Some("New header\nLine 2\n".to_string()));
+    fn position_index(&self, position: Position) -> Result<usize, KeyError> {
+        let first = |key: &str| {
+            self.index
+                .get(key)
+                .and_then(|positions| positions.first().copied())
+                .ok_or_else(|| KeyError::NotFound(key.to_string()))
+        };
+        match position {
+            Position::End => Ok(self.entries.len()),
+            Position::After(key) => first(key).map(|index| index + 1),
+            // before the comments that document the key, not between them
+            Position::Before(key) => first(key).map(|index| self.comments_above(index)),
+            Position::Section(name) => self
+                .section_end(name)
+                .ok_or_else(|| KeyError::SectionNotFound(name.to_string())),
+        }
     }
 
-    #[test]
-    fn test_delete_header() {
-        let content = "# Header\nKEY=value\n";
-        let mut env = EnvFile::parse(content).unwrap();
-        env.delete_header();
-        assert_eq!(env.get_header(), None);
+    // the index after the last key of a section, or after its heading if it has no keys
+    fn section_end(&self, name: &str) -> Option<usize> {
+        let heading = self.entries.iter().position(|entry| match entry {
+            Entry::Comment(text) => section_title(text).eq_ignore_ascii_case(name.trim()),
+            _ => false,
+        })?;
+
+        let mut end = heading + 1;
+        while let Some(Entry::Comment(_)) = self.entries.get(end) {
+            end += 1;
+        }
+        // a title can be set off from its keys by a blank line
+        if let (Some(Entry::Blank(_)), Some(Entry::KeyValue { .. })) =
+            (self.entries.get(end), self.entries.get(end + 1))
+        {
+            end += 1;
+        }
+        for (index, entry) in self.entries.iter().enumerate().skip(end) {
+            match entry {
+                Entry::Blank(_) => break,
+                Entry::KeyValue { .. } => end = index + 1,
+                _ => {}
+            }
+        }
+        Some(end)
     }
 
-    #[test]
-    fn test_to_string_preserves_format() {
-        let content =
-            "# Header\n\nKEY1=value1 # comment1\nKEY2=value2\n\n# middle comment\nKEY3=value3\n";
-        let env = EnvFile::parse(content).unwrap();
-        let output = env.to_string();
-        assert_eq!(output, content);
+    pub(crate) fn check_key(&self, key: &str) -> Result<(), KeyError> {
+        // systemd reads a line starting with `;` as a comment as well
+        if self.key_syntax.is_valid(key) && !self.dialect.is_comment(key) {
+            Ok(())
+        } else {
+            Err(KeyError::InvalidKey(key.to_string()))
+        }
     }
 
-    #[test]
-    fn test_blank_lines_preserved() {
+    // every key with the (1-based) line it starts on
+    fn key_lines(&self) -> Vec<(&str, usize)> {
+        self.entries
+            .iter()
+            .zip(self.entry_lines())
+            .filter_map(|(entry, line)| entry.key().map(|key| (key, line)))
+            .collect()
+    }
+
+    // the (1-based) line every entry starts on
+    pub(crate) fn entry_lines(&self) -> Vec<usize> {
+        let mut line = 1;
+        let mut lines = Vec::with_capacity(self.entries.len());
+        for entry in &self.entries {
+            lines.push(line);
+            // multiline values span more than one line
+            line += match entry {
+                Entry::KeyValue { raw, .. } => 1 + raw.matches('\n').count(),
+                Entry::Invalid(text) => 1 + text.matches('\n').count(),
+                _ => 1,
+            };
+        }
+        lines
+    }
+
+    // number of comment and blank lines before the first key
+    pub(crate) fn header_len(&self) -> usize {
+        self.entries
+            .iter()
+            .position(|entry| matches!(entry, Entry::KeyValue { .. }))
+            .unwrap_or(self.entries.len())
+    }
+
+    // the header ends at the last blank line before the first key, without
+    // one every comment before the first key is the header
+    pub(crate) fn header_end(&self) -> usize {
+        let first_key = self.header_len();
+        self.entries[..first_key]
+            .iter()
+            .rposition(|entry| matches!(entry, Entry::Blank(_)))
+            .map_or(first_key, |pos| pos + 1)
+    }
+
+    // the start of the comment lines right above the entry at `index`,
+    // comments of the header don't belong to the first key
+    pub(crate) fn comments_above(&self, index: usize) -> usize {
+        let header_end = self.header_end();
+        let mut start = index;
+        while start > header_end && matches!(self.entries[start - 1], Entry::Comment(_)) {
+            start -= 1;
+        }
+        start
+    }
+}
+
+impl fmt::Display for EnvFile {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let options = &self.serialize_options;
+        if options.bom {
+            write!(f, "\u{feff}")?;
+        }
+
+        let line_ending = options.line_ending.as_str();
+        for (i, entry) in self.entries.iter().enumerate() {
+            let line = entry.to_string();
+            // multiline values use the line ending of the file as well
+            match options.line_ending {
+                LineEnding::Lf => write!(f, "{}", line)?,
+                LineEnding::CrLf => write!(f, "{}", line.replace('\n', line_ending))?,
+            }
+            if options.final_newline || i + 1 < self.entries.len() {
+                write!(f, "{}", line_ending)?;
+            }
+        }
+
+        Ok(())
+    }
+}
+
+impl Entry {
+    pub(crate) fn new_key_value(
+        key: &str,
+        value: &str,
+        dialect: Dialect,
+    ) -> Result<Self, KeyError> {
+        let (quote, raw) = encode_value(value, Quote::None, dialect)
+            .ok_or_else(|| KeyError::UnquotableValue(key.to_string()))?;
+        Ok(Entry::KeyValue {
+            export: false,
+            key: key.to_string(),
+            value: value.to_string(),
+            quote,
+            raw,
+            comment: None,
+            trivia: Box::default(),
+        })
+    }
+
+    pub fn key(&self) -> Option<&str> {
+        match self {
+            Entry::KeyValue { key, .. } => Some(key),
+            _ => None,
+        }
+    }
+
+    pub fn value(&self) -> Option<&str> {
+        match self {
+            Entry::KeyValue { value, .. } => Some(value),
+            _ => None,
+        }
+    }
+
+    pub fn comment(&self) -> Option<&str> {
+        match self {
+            Entry::KeyValue { comment, .. } => comment.as_deref(),
+            _ => None,
+        }
+    }
+
+    pub(crate) fn set_key(&mut self, new_key: &str) {
+        if let Entry::KeyValue { key, .. } = self {
+            *key = new_key.to_string();
+        }
+    }
+
+    // keeps the quoting of the value unless it can't represent the new one
+    pub(crate) fn set_value(&mut self, new_value: &str, dialect: Dialect) -> Result<(), KeyError> {
+        if let Entry::KeyValue {
+            key,
+            value,
+            quote,
+            raw,
+            ..
+        } = self
+        {
+            (*quote, *raw) = encode_value(new_value, *quote, dialect)
+                .ok_or_else(|| KeyError::UnquotableValue(key.clone()))?;
+            *value = new_value.to_string();
+        }
+        Ok(())
+    }
+
+    pub(crate) fn set_comment(&mut self, new_comment: &str) {
+        if let Entry::KeyValue {
+            comment, trivia, ..
+        } = self
+        {
+            // a dangling `#` would otherwise end up after the new comment
+            if comment.is_none() {
+                trivia.trailing.clear();
+            }
+            *comment = Some(new_comment.to_string());
+        }
+    }
+
+    pub(crate) fn delete_comment(&mut self) {
+        if let Entry::KeyValue {
+            comment, trivia, ..
+        } = self
+            && comment.take().is_some()
+        {
+            trivia.trailing.clear();
+        }
+    }
+}
+
+impl fmt::Display for Entry {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Entry::KeyValue {
+                export,
+                key,
+                raw,
+                comment,
+                trivia,
+                ..
+            } => {
+                write!(f, "{}", trivia.indent)?;
+                if *export {
+                    write!(f, "export{}", trivia.export_gap)?;
+                }
+                write!(
+                    f,
+                    "{}{}={}{}",
+                    key, trivia.before_equals, trivia.after_equals, raw
+                )?;
+                if let Some(c) = comment {
+                    write!(f, "{}#{}{}", trivia.before_comment, trivia.comment_gap, c)?;
+                }
+                write!(f, "{}", trivia.trailing)
+            }
+            Entry::Comment(line) | Entry::Blank(line) | Entry::Invalid(line) => {
+                write!(f, "{}", line)
+            }
+        }
+    }
+}
+
+fn parse_line(
+    line: &str,
+    line_number: usize,
+    dialect: Dialect,
+    key_syntax: KeySyntax,
+) -> Result<Entry, ParseError> {
+    let trimmed = line.trim();
+
+    if trimmed.is_empty() {
+        return Ok(Entry::Blank(line.to_string()));
+    }
+
+    if dialect.is_comment(trimmed) {
+        return Ok(Entry::Comment(line.to_string()));
+    }
+
+    match parse_key_value(line, dialect, key_syntax) {
+        Ok(kv) => Ok(Entry::KeyValue {
+            export: kv.export,
+            key: kv.key.to_string(),
+            value: kv.value.value.into_owned(),
+            quote: kv.value.quote,
+            raw: kv.value.raw.to_string(),
+            comment: kv.comment.map(|s| s.to_string()),
+            trivia: Box::new(kv.trivia),
+        }),
+        Err((kind, offset)) => Err(ParseError::new(kind, line_number, line, offset)),
+    }
+}
+
+struct KeyValue<'a> {
+    export: bool,
+    key: &'a str,
+    value: Value<'a>,
+    comment: Option<&'a str>,
+    trivia: Trivia,
+}
+
+struct Value<'a> {
+    value: Cow<'a, str>,
+    raw: &'a str,
+    quote: Quote,
+    /// what follows the value, a comment or whitespace
+    tail: &'a str,
+}
+
+// on failure returns the kind of error and the byte offset it happened at
+fn parse_key_value(
+    line: &str,
+    dialect: Dialect,
+    key_syntax: KeySyntax,
+) -> Result<KeyValue<'_>, (ParseErrorKind, usize)> {
+    let mut trivia = Trivia::default();
+    // every slice below is a suffix of `line`, so its length gives its position
+    let offset = |rest: &str| line.len() - rest.len();
+
+    // find the first '=' sign
+    let Some(equal_pos) = line.find('=') else {
+        return Err((ParseErrorKind::InvalidLine, offset(line.trim_start())));
+    };
+    let (indent, lhs) = split_whitespace_prefix(&line[..equal_pos]);
+
+    // files meant to be sourced by a shell prefix keys with `export`
+    let (export, lhs) = match lhs.strip_prefix("export") {
+        Some(rest) if rest.starts_with(char::is_whitespace) && dialect.allows_export() => {
+            let (gap, rest) = split_whitespace_prefix(rest);
+            trivia.export_gap = gap.to_string();
+            (true, rest)
+        }
+        _ => (false, lhs),
+    };
+
+    let key = lhs.trim_end();
+
+    // key must not be empty
+    if key.is_empty() {
+        return Err((ParseErrorKind::MissingKey, equal_pos));
+    }
+    if !key_syntax.is_valid(key) {
+        // `lhs` ends at the `=`
+        return Err((ParseErrorKind::InvalidKey, equal_pos - lhs.len()));
+    }
+
+    trivia.indent = indent.to_string();
+    trivia.before_equals = lhs[key.len()..].to_string();
+
+    let rhs = &line[equal_pos + 1..];
+    let (after_equals, rest) = split_whitespace_prefix(rhs);
+    trivia.after_equals = after_equals.to_string();
+
+    // `KEY= value` is the command `value` with `KEY` set to nothing
+    let value_follows = !rest.is_empty() && !rest.starts_with('#');
+    if !dialect.allows_spaces_around_equals()
+        && (!trivia.before_equals.is_empty() || (!after_equals.is_empty() && value_follows))
+    {
+        return Err((ParseErrorKind::SpaceAroundEquals, equal_pos));
+    }
+
+    let value = parse_value(rhs, dialect).map_err(|(kind, pos)| (kind, equal_pos + 1 + pos))?;
+    let tail = value.tail;
+
+    // only a comment may follow the value
+    let (before_comment, rest) = split_whitespace_prefix(tail);
+    let comment = if rest.is_empty() {
+        trivia.trailing = tail.to_string();
+        None
+    } else {
+        let comment = rest
+            .strip_prefix('#')
+            .filter(|_| dialect.allows_inline_comments())
+            .ok_or((ParseErrorKind::TrailingText, offset(rest)))?;
+        let (gap, text) = split_whitespace_prefix(comment);
+        let comment = text.trim_end();
+        if comment.is_empty() {
+            // keep an empty `#` as it is
+            trivia.trailing = tail.to_string();
+            None
+        } else {
+            trivia.before_comment = before_comment.to_string();
+            trivia.comment_gap = gap.to_string();
+            trivia.trailing = text[comment.len()..].to_string();
+            Some(comment)
+        }
+    };
+
+    Ok(KeyValue {
+        export,
+        key,
+        value,
+        comment,
+        trivia,
+    })
+}
+
+// parses what follows the `=`, errors have the byte offset into `rhs`
+fn parse_value(rhs: &str, dialect: Dialect) -> Result<Value<'_>, (ParseErrorKind, usize)> {
+    let offset = |rest: &str| rhs.len() - rest.len();
+    let (after_equals, rest) = split_whitespace_prefix(rhs);
+    let quote = dialect.quote_of(rest);
+
+    if quote == Quote::None {
+        // look for comment after value
+        let end = dialect.find_inline_comment(rhs).unwrap_or(rhs.len());
+        let raw = rhs[after_equals.len()..end].trim_end();
+        if let Some(pos) = dialect.find_shell_syntax(raw) {
+            return Err((ParseErrorKind::ShellSyntax, offset(rest) + pos));
+        }
+        return Ok(Value {
+            value: dialect.unescape(quote, raw),
+            raw,
+            quote,
+            tail: &rest[raw.len()..],
+        });
+    }
+
+    // quoted values run until the closing quote, escaped quotes aside
+    let inner = &rest[1..];
+    let close_pos = dialect
+        .find_closing_quote(inner, quote)
+        .ok_or((ParseErrorKind::UnterminatedQuote, offset(rest)))?;
+    Ok(Value {
+        value: dialect.unescape(quote, &inner[..close_pos]),
+        raw: &rest[..close_pos + 2],
+        quote,
+        tail: &inner[close_pos + 1..],
+    })
+}
+
+fn split_whitespace_prefix(s: &str) -> (&str, &str) {
+    let rest = s.trim_start();
+    s.split_at(s.len() - rest.len())
+}
+
+// a quoted value that isn't closed on its own line continues on the
+// following lines, so the whole value is handed to the parser at once,
+// and so does a shell value ending with a backslash
+fn join_multiline<'a>(
+    line: &'a str,
+    lines: &mut impl Iterator<Item = &'a str>,
+    line_number: usize,
+    dialect: Dialect,
+) -> Result<Cow<'a, str>, ParseError> {
+    let quote_pos = open_quote(line, dialect);
+    if quote_pos.is_none() && !continues(line, dialect) {
+        return Ok(Cow::Borrowed(line));
+    }
+
+    let mut joined = line.to_string();
+    for next in lines.by_ref() {
+        joined.push('\n');
+        joined.push_str(next);
+        // only the new line is scanned, an escape can't reach past the end of
+        // a line, so a value that is never closed stays linear
+        let closed = match quote_pos {
+            Some(pos) => dialect
+                .find_closing_quote(next, dialect.quote_of(&line[pos..]))
+                .is_some(),
+            None => !dialect.continues_line(next),
+        };
+        if closed {
+            return Ok(Cow::Owned(joined));
+        }
+    }
+
+    match quote_pos {
+        Some(quote_pos) => Err(ParseError::new(
+            ParseErrorKind::UnterminatedQuote,
+            line_number,
+            line,
+            quote_pos,
+        )),
+        // a continuation on the last line continues with nothing
+        None => Ok(Cow::Owned(joined)),
+    }
+}
+
+// the position of the opening quote of a value that isn't closed
+fn open_quote(line: &str, dialect: Dialect) -> Option<usize> {
+    if dialect.is_comment(line.trim_start()) {
+        return None;
+    }
+    let equal_pos = line.find('=')?;
+    let rest = line[equal_pos + 1..].trim_start();
+    let quote = dialect.quote_of(rest);
+    if quote == Quote::None {
+        return None;
+    }
+    match dialect.find_closing_quote(&rest[1..], quote) {
+        Some(_) => None,
+        None => Some(line.len() - rest.len()),
+    }
+}
+
+fn continues(line: &str, dialect: Dialect) -> bool {
+    let trimmed = line.trim_start();
+    !dialect.is_comment(trimmed)
+        && line
+            .split_once('=')
+            .is_some_and(|(_, rest)| dialect.quote_of(rest.trim_start()) == Quote::None)
+        && dialect.continues_line(line)
+}
+
+// keeps the preferred quoting unless it can't represent the value, then
+// falls back to double, single and, where the dialect has them, backtick
+// quotes; `None` when no quoting reads back as the value
+fn encode_value(value: &str, preferred: Quote, dialect: Dialect) -> Option<(Quote, String)> {
+    let backtick = (dialect == Dialect::Node).then_some(Quote::Backtick);
+    [
+        Some(preferred),
+        Some(Quote::Double),
+        Some(Quote::Single),
+        backtick,
+    ]
+    .into_iter()
+    .flatten()
+    .map(|quote| (quote, dialect.quote_value(value, quote)))
+    .find(|(quote, raw)| reads_back(raw, *quote, value, dialect))
+}
+
+// whether the dialect reads `raw` as `value`, without references in it
+fn reads_back(raw: &str, quote: Quote, value: &str, dialect: Dialect) -> bool {
+    if quote == Quote::None && !can_be_unquoted(raw) {
+        return false;
+    }
+    parse_value(raw, dialect).is_ok_and(|parsed| {
+        parsed.quote == quote
+            && parsed.raw == raw
+            && parsed.value == value
+            && !dialect.has_reference(quote, raw)
+    })
+}
+
+// whether text reads the same without quotes, `$` references are left to the caller;
+// values with whitespace stay quoted so a shell can `source` the file, and an
+// empty value stays quoted so it reads as intentional
+pub(crate) fn can_be_unquoted(text: &str) -> bool {
+    !text.is_empty()
+        && !text.starts_with(['\'', '"', '`'])
+        && !text.contains(char::is_whitespace)
+        && find_inline_comment(text).is_none()
+}
+
+// `# --- Database ---` introduces the section "Database"
+fn section_title(comment: &str) -> &str {
+    comment
+        .trim_matches(|c: char| c == '#' || c == '-' || c == '=' || c == '*' || c.is_whitespace())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_parse_simple() {
+        let content = "KEY=value\n";
+        let env = EnvFile::parse(content).unwrap();
+        assert_eq!(env.list_keys(), vec!["KEY"]);
+        assert_eq!(env.get_value("KEY"), Some("value"));
+    }
+
+    #[test]
+    fn test_parse_with_comment() {
+        let content = "KEY=value # this is a comment\n";
+        let env = EnvFile::parse(content).unwrap();
+        assert_eq!(env.get_value("KEY"), Some("value"));
+        assert_eq!(env.get_comment("KEY"), Some("this is a comment"));
+    }
+
+    #[test]
+    fn test_parse_with_header() {
+        let content = "# Header line 1\n# Header line 2\n\nKEY=value\n";
+        let env = EnvFile::parse(content).unwrap();
+        assert_eq!(
+            env.get_header(),
+            Some("Header line 1\nHeader line 2\n".to_string())
+        );
+        assert_eq!(env.get_value("KEY"), Some("value"));
+    }
+
+    #[test]
+    fn test_set_value_preserves_comment() {
+        let content = "KEY=old # comment\n";
+        let mut env = EnvFile::parse(content).unwrap();
+        env.set_value("KEY", "new").unwrap();
+        assert_eq!(env.get_value("KEY"), Some("new"));
+        assert_eq!(env.get_comment("KEY"), Some("comment"));
+    }
+
+    #[test]
+    fn test_set_value_new_key() {
+        let content = "KEY1=value1\n";
+        let mut env = EnvFile::parse(content).unwrap();
+        env.set_value("KEY2", "value2").unwrap();
+        assert_eq!(env.list_keys(), vec!["KEY1", "KEY2"]);
+        assert_eq!(env.get_value("KEY2"), Some("value2"));
+    }
+
+    #[test]
+    fn test_delete_key_removes_comment() {
+        let content = "KEY=value # comment\n";
+        let mut env = EnvFile::parse(content).unwrap();
+        env.delete_key("KEY");
+        assert_eq!(env.list_keys().len(), 0);
+        assert_eq!(env.get_comment("KEY"), None);
+    }
+
+    #[test]
+    fn test_delete_comment_only() {
+        let content = "KEY=value # comment\n";
+        let mut env = EnvFile::parse(content).unwrap();
+        env.delete_comment("KEY");
+        assert_eq!(env.get_value("KEY"), Some("value"));
+        assert_eq!(env.get_comment("KEY"), None);
+    }
+
+    #[test]
+    fn test_set_comment() {
+        let content = "KEY=value\n";
+        let mut env = EnvFile::parse(content).unwrap();
+        env.set_comment("KEY", "new comment");
+        assert_eq!(env.get_comment("KEY"), Some("new comment"));
+    }
+
+    #[test]
+    fn test_set_header() {
+        let content = "KEY=value\n";
+        let mut env = EnvFile::parse(content).unwrap();
+        env.set_header("New header\nLine 2");
+        assert_eq!(env.get_header(), Some("New header\nLine 2\n".to_string()));
+    }
+
+    #[test]
+    fn test_delete_header() {
+        let content = "# Header\nKEY=value\n";
+        let mut env = EnvFile::parse(content).unwrap();
+        env.delete_header();
+        assert_eq!(env.get_header(), None);
+    }
+
+    #[test]
+    fn test_to_string_preserves_format() {
+        let content =
+            "# Header\n\nKEY1=value1 # comment1\nKEY2=value2\n\n# middle comment\nKEY3=value3\n";
+        let env = EnvFile::parse(content).unwrap();
+        let output = env.to_string();
+        assert_eq!(output, content);
+    }
+
+    #[test]
+    fn test_blank_lines_preserved() {
         let content = "KEY1=value1\n\nKEY2=value2\n";
         let env = EnvFile::parse(content).unwrap();
-        let output = env.to_string();
-        assert_eq!(output, content);
+        let output = env.to_string();
+        assert_eq!(output, content);
+    }
+
+    #[test]
+    fn test_comment_lines_preserved() {
+        let content = "KEY1=value1\n# This is a comment\nKEY2=value2\n";
+        let env = EnvFile::parse(content).unwrap();
+        let output = env.to_string();
+        assert_eq!(output, content);
+    }
+
+    #[test]
+    fn test_roundtrip_is_byte_exact() {
+        let content = "\n\n#header without space\n#\n\n  KEY1 = value1   #comment1  \nexport   KEY2=  \"two\"\t# c\n   \n\tKEY3=three #\nKEY4= # only comment\n  # indented comment\nKEY5=   \n";
+        let env = EnvFile::parse(content).unwrap();
+        assert_eq!(env.get_value("KEY1"), Some("value1"));
+        assert_eq!(env.get_comment("KEY1"), Some("comment1"));
+        assert_eq!(env.get_value("KEY2"), Some("two"));
+        assert_eq!(env.get_value("KEY3"), Some("three"));
+        assert_eq!(env.get_comment("KEY3"), None);
+        assert_eq!(env.get_value("KEY4"), Some(""));
+        assert_eq!(env.get_comment("KEY4"), Some("only comment"));
+        assert_eq!(env.get_value("KEY5"), Some(""));
+        assert_eq!(env.to_string(), content);
+    }
+
+    #[test]
+    fn test_set_same_value_is_noop() {
+        let content = "# header\nKEY = value   # comment\n\n";
+        let mut env = EnvFile::parse(content).unwrap();
+        env.set_value("KEY", "value").unwrap();
+        assert_eq!(env.to_string(), content);
+    }
+
+    #[test]
+    fn test_set_value_keeps_spacing() {
+        let content = "  KEY = old   #comment\nOTHER=1\n";
+        let mut env = EnvFile::parse(content).unwrap();
+        env.set_value("KEY", "new").unwrap();
+        assert_eq!(env.to_string(), "  KEY = new   #comment\nOTHER=1\n");
+    }
+
+    #[test]
+    fn test_set_comment_replaces_dangling_hash() {
+        let content = "KEY=value #\n";
+        let mut env = EnvFile::parse(content).unwrap();
+        env.set_comment("KEY", "comment");
+        assert_eq!(env.to_string(), "KEY=value # comment\n");
+        env.delete_comment("KEY");
+        assert_eq!(env.to_string(), "KEY=value\n");
+    }
+
+    #[test]
+    fn test_set_header_keeps_entries() {
+        let content = "\n#old header\n\nKEY=value\n";
+        let mut env = EnvFile::parse(content).unwrap();
+        env.set_header("new\n\nheader");
+        assert_eq!(env.to_string(), "# new\n#\n# header\n\nKEY=value\n");
+        assert_eq!(env.get_header(), Some("new\n\nheader\n".to_string()));
+    }
+
+    #[test]
+    fn test_duplicates_with_line_numbers() {
+        let content = "# header\nA=1\nB=\"multi\nline\"\nA=2\nC=3\nB=4\nA=5\n";
+        let env = EnvFile::parse(content).unwrap();
+        assert_eq!(
+            env.duplicates(),
+            vec![("A", vec![2, 5, 8]), ("B", vec![3, 7])]
+        );
+    }
+
+    #[test]
+    fn test_no_duplicates() {
+        let env = EnvFile::parse("A=1\nB=2\n").unwrap();
+        assert!(env.duplicates().is_empty());
+    }
+
+    #[test]
+    fn test_get_value_with_policy() {
+        let content = "A=1 # first\nB=2\nA=3 # last\n";
+        let env = EnvFile::parse(content).unwrap();
+        assert_eq!(env.get_value("A"), Some("1"));
+        assert_eq!(env.get_value_with("A", DuplicatePolicy::First), Some("1"));
+        assert_eq!(env.get_value_with("A", DuplicatePolicy::Last), Some("3"));
+        assert_eq!(env.get_value_with("B", DuplicatePolicy::Last), Some("2"));
+    }
+
+    #[test]
+    fn test_list_values_includes_duplicates() {
+        let env = EnvFile::parse("A=1\nB=2\nA=3\n").unwrap();
+        assert_eq!(env.list_values(), vec![("A", "1"), ("B", "2"), ("A", "3")]);
+    }
+
+    #[test]
+    fn test_dedupe_keeps_last() {
+        let content = "A=1 # first\nB=2\nA=3 # last\n";
+        let mut env = EnvFile::parse(content).unwrap();
+        env.dedupe(DuplicatePolicy::Last);
+        assert_eq!(env.to_string(), "B=2\nA=3 # last\n");
+    }
+
+    #[test]
+    fn test_dedupe_keeps_first() {
+        let content = "A=1 # first\nB=2\nA=3 # last\n";
+        let mut env = EnvFile::parse(content).unwrap();
+        env.dedupe(DuplicatePolicy::First);
+        assert_eq!(env.to_string(), "A=1 # first\nB=2\n");
+    }
+
+    #[test]
+    fn test_crlf_roundtrip() {
+        let content = "# header\r\n\r\nA=1 # comment\r\nB=\"multi\r\nline\"\r\n";
+        let mut env = EnvFile::parse(content).unwrap();
+        assert_eq!(env.get_value("A"), Some("1"));
+        assert_eq!(env.get_comment("A"), Some("comment"));
+        assert_eq!(env.get_value("B"), Some("multi\nline"));
+        assert_eq!(env.to_string(), content);
+
+        env.set_value("A", "2").unwrap();
+        env.set_value("C", "3").unwrap();
+        assert_eq!(
+            env.to_string(),
+            "# header\r\n\r\nA=2 # comment\r\nB=\"multi\r\nline\"\r\nC=3\r\n"
+        );
+    }
+
+    #[test]
+    fn test_bom_roundtrip() {
+        let content = "\u{feff}# header\nA=1\n";
+        let mut env = EnvFile::parse(content).unwrap();
+        assert_eq!(env.get_header(), Some("header\n".to_string()));
+        assert_eq!(env.list_keys(), vec!["A"]);
+        assert_eq!(env.to_string(), content);
+
+        env.set_value("A", "2").unwrap();
+        assert_eq!(env.to_string(), "\u{feff}# header\nA=2\n");
+    }
+
+    #[test]
+    fn test_bom_before_first_key() {
+        let content = "\u{feff}A=1\r\n";
+        let env = EnvFile::parse(content).unwrap();
+        assert_eq!(env.list_keys(), vec!["A"]);
+        assert_eq!(env.to_string(), content);
     }
 
     #[test]
-    fn test_comment_lines_preserved() {
-        let content = "KEY1=value1\n# This is a comment\nKEY2=value2\n";
+    fn test_missing_final_newline_roundtrip() {
+        let content = "A=1\nB=2";
+        let mut env = EnvFile::parse(content).unwrap();
+        assert_eq!(env.to_string(), content);
+
+        env.set_value("C", "3").unwrap();
+        assert_eq!(env.to_string(), "A=1\nB=2\nC=3");
+
+        let content = "A=1\r\nB=2";
         let env = EnvFile::parse(content).unwrap();
-        let output = env.to_string();
-        assert_eq!(output, content);
+        assert_eq!(env.to_string(), content);
+    }
+
+    #[test]
+    fn test_set_serialize_options() {
+        let mut env = EnvFile::parse("A=1\nB=2\n").unwrap();
+        assert_eq!(env.serialize_options(), &SerializeOptions::default());
+        env.set_serialize_options(SerializeOptions {
+            line_ending: LineEnding::CrLf,
+            bom: true,
+            final_newline: false,
+        });
+        assert_eq!(env.to_string(), "\u{feff}A=1\r\nB=2");
+    }
+
+    #[test]
+    fn test_parse_with_lenient() {
+        let options = ParseOptions {
+            lenient: true,
+            ..Default::default()
+        };
+        let env = EnvFile::parse_with("A=1\noops\n", &options).unwrap();
+        assert_eq!(env.list_keys(), vec!["A"]);
+        assert!(EnvFile::parse_with("A=1\noops\n", &ParseOptions::default()).is_err());
+    }
+
+    #[test]
+    fn test_empty_file() {
+        let mut env = EnvFile::parse("").unwrap();
+        assert_eq!(env.to_string(), "");
+        env.set_value("A", "1").unwrap();
+        assert_eq!(env.to_string(), "A=1\n");
     }
 
     #[test]
@@ -397,6 +1496,261 @@ mod tests {
         assert_eq!(env.get_comment("KEY"), Some("comment"));
     }
 
+    #[test]
+    fn test_double_quoted_value() {
+        let content = "KEY=\"hello world\"\n";
+        let env = EnvFile::parse(content).unwrap();
+        assert_eq!(env.get_value("KEY"), Some("hello world"));
+        assert_eq!(env.to_string(), content);
+    }
+
+    #[test]
+    fn test_single_quoted_value() {
+        let content = "KEY='hello world'\n";
+        let env = EnvFile::parse(content).unwrap();
+        assert_eq!(env.get_value("KEY"), Some("hello world"));
+        assert_eq!(env.to_string(), content);
+    }
+
+    #[test]
+    fn test_quoted_value_keeps_inner_whitespace() {
+        let content = "KEY=\"  padded  \"\n";
+        let env = EnvFile::parse(content).unwrap();
+        assert_eq!(env.get_value("KEY"), Some("  padded  "));
+    }
+
+    #[test]
+    fn test_quoted_value_with_comment() {
+        let content = "KEY=\"hello world\" # comment\n";
+        let env = EnvFile::parse(content).unwrap();
+        assert_eq!(env.get_value("KEY"), Some("hello world"));
+        assert_eq!(env.get_comment("KEY"), Some("comment"));
+        assert_eq!(env.to_string(), content);
+    }
+
+    #[test]
+    fn test_set_value_preserves_quotes() {
+        let content = "KEY='old'\n";
+        let mut env = EnvFile::parse(content).unwrap();
+        env.set_value("KEY", "new value").unwrap();
+        assert_eq!(env.to_string(), "KEY='new value'\n");
+    }
+
+    #[test]
+    fn test_hash_inside_value_is_not_a_comment() {
+        let content = "DB_PASSWORD=abc#123\n";
+        let env = EnvFile::parse(content).unwrap();
+        assert_eq!(env.get_value("DB_PASSWORD"), Some("abc#123"));
+        assert_eq!(env.get_comment("DB_PASSWORD"), None);
+        assert_eq!(env.to_string(), content);
+    }
+
+    #[test]
+    fn test_hash_in_url_roundtrip() {
+        let content = "URL=https://x/#frag # the url\n";
+        let mut env = EnvFile::parse(content).unwrap();
+        assert_eq!(env.get_value("URL"), Some("https://x/#frag"));
+        assert_eq!(env.get_comment("URL"), Some("the url"));
+        env.set_value("URL", "https://y/#other").unwrap();
+        assert_eq!(env.to_string(), "URL=https://y/#other # the url\n");
+    }
+
+    #[test]
+    fn test_hash_inside_quotes_is_not_a_comment() {
+        let content = "PASSWORD=\"abc #123\" # comment\n";
+        let env = EnvFile::parse(content).unwrap();
+        assert_eq!(env.get_value("PASSWORD"), Some("abc #123"));
+        assert_eq!(env.get_comment("PASSWORD"), Some("comment"));
+        assert_eq!(env.to_string(), content);
+    }
+
+    #[test]
+    fn test_value_starting_with_hash() {
+        let content = "KEY=#value\n";
+        let env = EnvFile::parse(content).unwrap();
+        assert_eq!(env.get_value("KEY"), Some("#value"));
+        assert_eq!(env.get_comment("KEY"), None);
+    }
+
+    #[test]
+    fn test_multiline_double_quoted_value() {
+        let content = "TLS_KEY=\"-----BEGIN KEY-----\nabc\n-----END KEY-----\"\nNEXT=value\n";
+        let env = EnvFile::parse(content).unwrap();
+        assert_eq!(
+            env.get_value("TLS_KEY"),
+            Some("-----BEGIN KEY-----\nabc\n-----END KEY-----")
+        );
+        assert_eq!(env.get_value("NEXT"), Some("value"));
+        assert_eq!(env.list_keys(), vec!["TLS_KEY", "NEXT"]);
+        assert_eq!(env.to_string(), content);
+    }
+
+    #[test]
+    fn test_multiline_single_quoted_value_with_comment() {
+        let content = "KEY='line1\nline2' # comment\n";
+        let env = EnvFile::parse(content).unwrap();
+        assert_eq!(env.get_value("KEY"), Some("line1\nline2"));
+        assert_eq!(env.get_comment("KEY"), Some("comment"));
+        assert_eq!(env.to_string(), content);
+    }
+
+    #[test]
+    fn test_multiline_value_with_blank_and_hash_lines() {
+        let content = "KEY=\"line1\n\n# not a comment\nline4\"\n";
+        let env = EnvFile::parse(content).unwrap();
+        assert_eq!(
+            env.get_value("KEY"),
+            Some("line1\n\n# not a comment\nline4")
+        );
+        assert_eq!(env.to_string(), content);
+    }
+
+    #[test]
+    fn test_set_multiline_value_adds_quotes() {
+        let content = "KEY=value\n";
+        let mut env = EnvFile::parse(content).unwrap();
+        env.set_value("KEY", "line1\nline2").unwrap();
+        env.set_value("NEW", "a\nb").unwrap();
+        assert_eq!(env.to_string(), "KEY=\"line1\nline2\"\nNEW=\"a\nb\"\n");
+        let env = EnvFile::parse(&env.to_string()).unwrap();
+        assert_eq!(env.get_value("KEY"), Some("line1\nline2"));
+        assert_eq!(env.get_value("NEW"), Some("a\nb"));
+    }
+
+    #[test]
+    fn test_double_quoted_escape_sequences() {
+        let content = "KEY=\"a\\nb\\tc \\\"q\\\" \\\\ \\$HOME \\x\"\n";
+        let env = EnvFile::parse(content).unwrap();
+        assert_eq!(env.get_value("KEY"), Some("a\nb\tc \"q\" \\ $HOME \\x"));
+        assert_eq!(env.to_string(), content);
+    }
+
+    #[test]
+    fn test_single_quoted_value_has_no_escapes() {
+        let content = "KEY='a\\nb'\n";
+        let env = EnvFile::parse(content).unwrap();
+        assert_eq!(env.get_value("KEY"), Some(r"a\nb"));
+    }
+
+    #[test]
+    fn test_escaped_quote_does_not_close_value() {
+        let content = "KEY=\"say \\\"hi\\\"\" # comment\n";
+        let env = EnvFile::parse(content).unwrap();
+        assert_eq!(env.get_value("KEY"), Some("say \"hi\""));
+        assert_eq!(env.get_comment("KEY"), Some("comment"));
+    }
+
+    #[test]
+    fn test_set_value_escapes_and_reads_back() {
+        let values = [
+            "plain",
+            "with space",
+            "  padded  ",
+            "say \"hi\"",
+            "it's",
+            "back\\slash",
+            "$HOME",
+            "line1\nline2",
+            "tab\there",
+            "a #b",
+            "\"leading quote",
+        ];
+        for quote in ["KEY=old\n", "KEY='old'\n", "KEY=\"old\"\n"] {
+            for value in values {
+                let mut env = EnvFile::parse(quote).unwrap();
+                env.set_value("KEY", value).unwrap();
+                let env = EnvFile::parse(&env.to_string()).unwrap();
+                assert_eq!(env.get_value("KEY"), Some(value), "{:?}", env.to_string());
+            }
+        }
+    }
+
+    #[test]
+    fn test_set_value_quotes_only_when_needed() {
+        let mut env = EnvFile::parse("A=1\nB='2'\n").unwrap();
+        env.set_value("A", "plain").unwrap();
+        env.set_value("B", "it's").unwrap();
+        env.set_value("C", "with # hash").unwrap();
+        env.set_value("D", "hello world").unwrap();
+        assert_eq!(
+            env.to_string(),
+            "A=plain\nB=\"it's\"\nC=\"with # hash\"\nD=\"hello world\"\n"
+        );
+    }
+
+    #[test]
+    fn test_unterminated_quote_errors() {
+        let content = "KEY=\"value\nOTHER=value\n";
+        let result = EnvFile::parse(content);
+        assert!(result.is_err());
+        assert!(
+            result
+                .unwrap_err()
+                .to_string()
+                .contains("Unterminated quoted value")
+        );
+    }
+
+    #[test]
+    fn test_unterminated_quote_in_long_file() {
+        let content = format!("KEY=\"value\n{}", "OTHER=value\n".repeat(20_000));
+        let error = EnvFile::parse(&content).unwrap_err();
+        assert_eq!(error.kind, ParseErrorKind::UnterminatedQuote);
+        assert_eq!(error.line, 1);
+    }
+
+    #[test]
+    fn test_multiline_value_with_escapes_at_line_ends() {
+        let content = "KEY=\"a\\\\\n\\\"b\\\"\nc\"\nNEXT=1\n";
+        let env = EnvFile::parse(content).unwrap();
+        assert_eq!(env.get_value("KEY"), Some("a\\\n\"b\"\nc"));
+        assert_eq!(env.get_value("NEXT"), Some("1"));
+    }
+
+    #[test]
+    fn test_text_after_closing_quote_errors() {
+        let content = "KEY=\"value\" trailing\n";
+        assert!(EnvFile::parse(content).is_err());
+    }
+
+    #[test]
+    fn test_export_prefix() {
+        let content = "export FOO=bar # comment\nBAZ=qux\n";
+        let env = EnvFile::parse(content).unwrap();
+        assert_eq!(env.list_keys(), vec!["FOO", "BAZ"]);
+        assert_eq!(env.get_value("FOO"), Some("bar"));
+        assert_eq!(env.to_string(), content);
+    }
+
+    #[test]
+    fn test_export_as_key_name() {
+        let content = "export=1\nexported=2\n";
+        let env = EnvFile::parse(content).unwrap();
+        assert_eq!(env.list_keys(), vec!["export", "exported"]);
+        assert_eq!(env.get_value("export"), Some("1"));
+    }
+
+    #[test]
+    fn test_set_value_keeps_export() {
+        let content = "export FOO=bar\n";
+        let mut env = EnvFile::parse(content).unwrap();
+        env.set_value("FOO", "new").unwrap();
+        assert_eq!(env.to_string(), "export FOO=new\n");
+    }
+
+    #[test]
+    fn test_set_export_all() {
+        let content = "export FOO=bar\n# comment\nBAZ=qux\n";
+        let mut env = EnvFile::parse(content).unwrap();
+        env.set_export(true);
+        assert_eq!(
+            env.to_string(),
+            "export FOO=bar\n# comment\nexport BAZ=qux\n"
+        );
+        env.set_export(false);
+        assert_eq!(env.to_string(), "FOO=bar\n# comment\nBAZ=qux\n");
+    }
+
     #[test]
     fn test_invalid_line_after_key_errors() {
         let content = "KEY=value\ninvalid line\n";
@@ -418,10 +1772,437 @@ mod tests {
         );
     }
 
+    #[test]
+    fn test_error_positions() {
+        let cases = [
+            ("A=1\n  oops\n", ParseErrorKind::InvalidLine, 2, 3, "  oops"),
+            (
+                "# h\noops\nA=1\n",
+                ParseErrorKind::InvalidLineBeforeFirstKey,
+                2,
+                1,
+                "oops",
+            ),
+            (
+                "A=1\n =value\n",
+                ParseErrorKind::MissingKey,
+                2,
+                2,
+                " =value",
+            ),
+            (
+                "A=1\nB = \"open\nC=3\n",
+                ParseErrorKind::UnterminatedQuote,
+                2,
+                5,
+                "B = \"open",
+            ),
+            (
+                "A=1\nexport MY KEY=x\n",
+                ParseErrorKind::InvalidKey,
+                2,
+                8,
+                "export MY KEY=x",
+            ),
+            (
+                "A=1\nB='x' y\n",
+                ParseErrorKind::TrailingText,
+                2,
+                7,
+                "B='x' y",
+            ),
+            (
+                "A=\"1\n2\" y\n",
+                ParseErrorKind::TrailingText,
+                2,
+                4,
+                "2\" y",
+            ),
+            ("A=\"1\n2\"\nB\n", ParseErrorKind::InvalidLine, 3, 1, "B"),
+        ];
+        for (content, kind, line, column, text) in cases {
+            let error = EnvFile::parse(content).unwrap_err();
+            assert_eq!(error.kind, kind, "{:?}", content);
+            assert_eq!(error.line, line, "{:?}", content);
+            assert_eq!(error.column, column, "{:?}", content);
+            assert_eq!(error.text, text, "{:?}", content);
+        }
+    }
+
+    #[test]
+    fn test_parse_lenient_collects_all_errors() {
+        let content = "# header\noops\nA=1\nB='x' y\nC=\"open\nD=4\n=5\n";
+        let (env, errors) = EnvFile::parse_lenient(content);
+        assert_eq!(env.list_keys(), vec!["A", "D"]);
+        assert_eq!(env.get_value("D"), Some("4"));
+        let found: Vec<(ParseErrorKind, usize)> = errors.iter().map(|e| (e.kind, e.line)).collect();
+        assert_eq!(
+            found,
+            vec![
+                (ParseErrorKind::InvalidLineBeforeFirstKey, 2),
+                (ParseErrorKind::TrailingText, 4),
+                (ParseErrorKind::UnterminatedQuote, 5),
+                (ParseErrorKind::MissingKey, 7),
+            ]
+        );
+        assert_eq!(env.to_string(), content);
+    }
+
+    #[test]
+    fn test_parse_lenient_edits_keep_invalid_lines() {
+        let content = "A=1\nnot valid\nB=2\n";
+        let (mut env, errors) = EnvFile::parse_lenient(content);
+        assert_eq!(errors.len(), 1);
+        env.set_value("B", "3").unwrap();
+        assert_eq!(env.to_string(), "A=1\nnot valid\nB=3\n");
+    }
+
+    #[test]
+    fn test_parse_lenient_line_numbers_after_multiline_invalid() {
+        let content = "A=\"1\n2\" y\nB=2\nB=3\n";
+        let (env, errors) = EnvFile::parse_lenient(content);
+        assert_eq!(errors.len(), 1);
+        assert_eq!(env.duplicates(), vec![("B", vec![3, 4])]);
+    }
+
+    #[test]
+    fn test_parse_strict_stops_at_first_error() {
+        let content = "A=1\noops\nB='x' y\n";
+        let error = EnvFile::parse(content).unwrap_err();
+        assert_eq!(error.kind, ParseErrorKind::InvalidLine);
+        assert_eq!(error.line, 2);
+    }
+
     #[test]
     fn test_blank_lines_before_first_key_allowed() {
         let content = "\n\n# header\n\nKEY=value\n";
         let env = EnvFile::parse(content).unwrap();
         assert_eq!(env.get_value("KEY"), Some("value"));
     }
+
+    #[test]
+    fn test_iter_skips_non_keys() {
+        let env = EnvFile::parse("# header\n\nA=1 # one\nB=2\n").unwrap();
+        let keys: Vec<_> = env
+            .iter()
+            .map(|entry| (entry.key(), entry.value(), entry.comment(), entry.index()))
+            .collect();
+        assert_eq!(keys, vec![("A", "1", Some("one"), 2), ("B", "2", None, 3)]);
+    }
+
+    #[test]
+    fn test_iter_mut() {
+        let mut env = EnvFile::parse("A=1 # one\nB='2'\n").unwrap();
+        for mut entry in env.iter_mut() {
+            let value = format!("{}0", entry.value());
+            entry.set_value(&value).unwrap();
+            entry.delete_comment();
+        }
+        assert_eq!(env.to_string(), "A=10\nB='20'\n");
+    }
+
+    #[test]
+    fn test_index_follows_inserts_and_deletes() {
+        let mut env = EnvFile::parse("# header\n\nA=1\nB=2\nA=3\n").unwrap();
+        env.delete_header();
+        env.delete_key("B");
+        env.set_value("C", "4").unwrap();
+        env.set_header("new header");
+        assert_eq!(env.get_value_with("A", DuplicatePolicy::Last), Some("3"));
+        assert_eq!(env.get_value("C"), Some("4"));
+        assert_eq!(env.get_value("B"), None);
+
+        env.dedupe(DuplicatePolicy::Last);
+        env.set_comment("A", "last");
+        assert_eq!(env.to_string(), "# new header\n\nA=3 # last\nC=4\n");
+    }
+
+    #[test]
+    fn test_rename_key_keeps_position_and_comment() {
+        let mut env = EnvFile::parse("A=1\nexport OLD = 'x' # note\nB=2\nOLD=y\n").unwrap();
+        env.rename_key("OLD", "NEW", false).unwrap();
+        assert_eq!(
+            env.to_string(),
+            "A=1\nexport NEW = 'x' # note\nB=2\nNEW=y\n"
+        );
+        assert_eq!(env.get_value("NEW"), Some("x"));
+        assert_eq!(env.get_value("OLD"), None);
+    }
+
+    #[test]
+    fn test_rename_key_errors() {
+        let mut env = EnvFile::parse("A=1\nB=2\n").unwrap();
+        assert_eq!(
+            env.rename_key("C", "D", false),
+            Err(KeyError::NotFound("C".to_string()))
+        );
+        assert_eq!(
+            env.rename_key("A", "B", false),
+            Err(KeyError::AlreadyExists("B".to_string()))
+        );
+        assert_eq!(env.to_string(), "A=1\nB=2\n");
+    }
+
+    #[test]
+    fn test_rename_key_force_replaces_existing() {
+        let mut env = EnvFile::parse("A=1\nB=2\n").unwrap();
+        env.rename_key("B", "A", true).unwrap();
+        assert_eq!(env.to_string(), "A=2\n");
+        assert_eq!(env.get_value("A"), Some("2"));
+    }
+
+    #[test]
+    fn test_set_value_at_anchor() {
+        let mut env = EnvFile::parse("A=1 # one\nB=2\n").unwrap();
+        env.set_value_at("C", "3", Position::After("A")).unwrap();
+        env.set_value_at("D", "4", Position::Before("A")).unwrap();
+        env.set_value_at("B", "5", Position::After("D")).unwrap();
+        assert_eq!(env.to_string(), "D=4\nA=1 # one\nC=3\nB=5\n");
+        assert_eq!(
+            env.set_value_at("E", "6", Position::After("MISSING")),
+            Err(KeyError::NotFound("MISSING".to_string()))
+        );
+        // an existing key keeps its place, the anchor isn't needed
+        env.set_value_at("A", "7", Position::After("MISSING"))
+            .unwrap();
+        assert_eq!(env.to_string(), "D=4\nA=7 # one\nC=3\nB=5\n");
+    }
+
+    #[test]
+    fn test_set_value_before_commented_key() {
+        let mut env = EnvFile::parse("A=1\n# the port\n# in use\nPORT=1\n").unwrap();
+        env.set_value_at("NEW", "x", Position::Before("PORT"))
+            .unwrap();
+        assert_eq!(
+            env.to_string(),
+            "A=1\nNEW=x\n# the port\n# in use\nPORT=1\n"
+        );
+
+        // the header stays at the top
+        let mut env = EnvFile::parse("# header\nPORT=1\n").unwrap();
+        env.set_value_at("NEW", "x", Position::Before("PORT"))
+            .unwrap();
+        assert_eq!(env.to_string(), "# header\nNEW=x\nPORT=1\n");
+
+        let mut env = EnvFile::parse("# header\n\n# the port\nPORT=1\n").unwrap();
+        env.set_value_at("NEW", "x", Position::Before("PORT"))
+            .unwrap();
+        assert_eq!(env.to_string(), "# header\n\nNEW=x\n# the port\nPORT=1\n");
+    }
+
+    #[test]
+    fn test_set_value_at_section() {
+        let content = "# App\nNAME=envq\n\n# --- Database ---\n# connection\nDB_HOST=localhost\n# the port\nDB_PORT=5432\n\n# Cache\n";
+        let mut env = EnvFile::parse(content).unwrap();
+        env.set_value_at("DB_USER", "admin", Position::Section("database"))
+            .unwrap();
+        env.set_value_at("REDIS_URL", "redis://", Position::Section("Cache"))
+            .unwrap();
+        assert_eq!(
+            env.to_string(),
+            "# App\nNAME=envq\n\n# --- Database ---\n# connection\nDB_HOST=localhost\n# the port\nDB_PORT=5432\nDB_USER=admin\n\n# Cache\nREDIS_URL=redis://\n"
+        );
+        assert_eq!(
+            env.set_value_at("X", "1", Position::Section("Mail")),
+            Err(KeyError::SectionNotFound("Mail".to_string()))
+        );
+
+        // a title set off by a blank line stays with its keys
+        let mut env = EnvFile::parse("# Database\n\nDB_HOST=x\nDB_PORT=1\n\nOTHER=y\n").unwrap();
+        env.set_value_at("DB_USER", "u", Position::Section("Database"))
+            .unwrap();
+        assert_eq!(
+            env.to_string(),
+            "# Database\n\nDB_HOST=x\nDB_PORT=1\nDB_USER=u\n\nOTHER=y\n"
+        );
+    }
+
+    #[test]
+    fn test_key_syntax() {
+        let cases = [
+            ("DB_HOST", true, true, true),
+            ("_private1", true, true, true),
+            ("1FOO", false, false, true),
+            ("app.name", false, true, true),
+            ("MY-KEY", false, true, true),
+            ("-KEY", false, false, true),
+            ("CLÉ", false, false, true),
+            ("$(id)", false, false, true),
+            ("A#B", false, false, true),
+            ("#A", false, false, false),
+            ("X=Y", false, false, false),
+            ("MY KEY", false, false, false),
+            ("TAB\tKEY", false, false, false),
+        ];
+        for (key, posix, compose, permissive) in cases {
+            assert_eq!(KeySyntax::Posix.is_valid(key), posix, "{}", key);
+            assert_eq!(KeySyntax::Compose.is_valid(key), compose, "{}", key);
+            assert_eq!(KeySyntax::Permissive.is_valid(key), permissive, "{}", key);
+        }
+        assert!(!KeySyntax::Permissive.is_valid(""));
+    }
+
+    #[test]
+    fn test_parse_with_key_syntax() {
+        let options = ParseOptions {
+            key_syntax: Some(KeySyntax::Compose),
+            ..Default::default()
+        };
+        let env = EnvFile::parse_with("app.name=envq\n", &options).unwrap();
+        assert_eq!(env.get_value("app.name"), Some("envq"));
+
+        let options = ParseOptions {
+            key_syntax: Some(KeySyntax::Posix),
+            ..Default::default()
+        };
+        let error = EnvFile::parse_with("app.name=envq\n", &options).unwrap_err();
+        assert_eq!(error.kind, ParseErrorKind::InvalidKey);
+
+        let (env, errors) = EnvFile::parse_lenient_with("app.name=envq\nA=1\n", &options);
+        assert_eq!(errors.len(), 1);
+        assert_eq!(env.list_keys(), vec!["A"]);
+    }
+
+    #[test]
+    fn test_edits_reject_invalid_keys() {
+        let options = ParseOptions {
+            key_syntax: Some(KeySyntax::Posix),
+            ..Default::default()
+        };
+        let mut env = EnvFile::parse_with("A=1\n", &options).unwrap();
+        assert_eq!(
+            env.set_value_at("app.name", "x", Position::End),
+            Err(KeyError::InvalidKey("app.name".to_string()))
+        );
+        assert_eq!(
+            env.rename_key("A", "1A", false),
+            Err(KeyError::InvalidKey("1A".to_string()))
+        );
+        assert_eq!(
+            env.set_value("MY KEY", "x"),
+            Err(KeyError::InvalidKey("MY KEY".to_string()))
+        );
+        assert!(env.entry("MY KEY").or_insert("x").is_err());
+        // what is written always parses again
+        assert_eq!(env.to_string(), "A=1\n");
+        env.set_value("A", "2").unwrap();
+
+        env.set_key_syntax(KeySyntax::Compose);
+        env.set_value_at("app.name", "x", Position::End).unwrap();
+        assert_eq!(env.to_string(), "A=2\napp.name=x\n");
+    }
+
+    #[test]
+    fn test_accepted_keys_read_back() {
+        let keys = [
+            "A", "app.name", "MY-KEY", "CLÉ", "$(id)", "A#B", "export", "'A'", "X=Y", "=X", "#X",
+            ";X",
+        ];
+        let dialects = [
+            Dialect::Envq,
+            Dialect::Bash,
+            Dialect::Compose,
+            Dialect::Systemd,
+            Dialect::PythonDotenv,
+            Dialect::Node,
+        ];
+        let syntaxes = [KeySyntax::Posix, KeySyntax::Compose, KeySyntax::Permissive];
+        for dialect in dialects {
+            for key_syntax in syntaxes {
+                let options = ParseOptions {
+                    dialect,
+                    key_syntax: Some(key_syntax),
+                    ..Default::default()
+                };
+                for key in keys {
+                    let mut env = EnvFile::parse_with("", &options).unwrap();
+                    let mut renamed = EnvFile::parse_with("A=1\n", &options).unwrap();
+                    if env.set_value(key, "v").is_err() {
+                        assert!(renamed.rename_key("A", key, false).is_err(), "{key}");
+                        continue;
+                    }
+                    renamed.rename_key("A", key, false).unwrap();
+                    for env in [env, renamed] {
+                        let parsed = EnvFile::parse_with(&env.to_string(), &options);
+                        assert_eq!(
+                            parsed.map(|env| env.list_keys().join(",")),
+                            Ok(key.to_string()),
+                            "{dialect:?} {key_syntax:?}"
+                        );
+                    }
+                }
+            }
+        }
+    }
+
+    fn parse_dialect(content: &str, dialect: Dialect) -> Result<EnvFile, ParseError> {
+        let options = ParseOptions {
+            dialect,
+            ..Default::default()
+        };
+        EnvFile::parse_with(content, &options)
+    }
+
+    #[test]
+    fn test_dialect_parse_errors() {
+        let error = parse_dialect("A = b\n", Dialect::Bash).unwrap_err();
+        assert_eq!(error.kind, ParseErrorKind::SpaceAroundEquals);
+        let error = parse_dialect("A=hello world\n", Dialect::Bash).unwrap_err();
+        assert_eq!(error.kind, ParseErrorKind::ShellSyntax);
+        let error = parse_dialect("A=\"b\" # c\n", Dialect::Systemd).unwrap_err();
+        assert_eq!(error.kind, ParseErrorKind::TrailingText);
+        let error = parse_dialect("app.name=envq\n", Dialect::Systemd).unwrap_err();
+        assert_eq!(error.kind, ParseErrorKind::InvalidKey);
+
+        let env = parse_dialect("; comment\nA=b # c\n", Dialect::Systemd).unwrap();
+        assert_eq!(env.get_value("A"), Some("b # c"));
+        let env = parse_dialect("A=b#c\nB=`it's`\n", Dialect::Node).unwrap();
+        assert_eq!(env.get_value("A"), Some("b"));
+        assert_eq!(env.get_value("B"), Some("it's"));
+        assert_eq!(env.dialect(), Dialect::Node);
+    }
+
+    #[test]
+    fn test_dialect_set_value_reads_back() {
+        let mut env = parse_dialect("", Dialect::Node).unwrap();
+        env.set_value("A", "b#c").unwrap();
+        env.set_value("B", "b\nc").unwrap();
+        assert_eq!(env.to_string(), "A=\"b#c\"\nB=\"b\nc\"\n");
+        // node can't escape quotes, a value with both kinds needs backticks
+        let cases = [
+            ("it's \"q\"", "`it's \"q\"`"),
+            ("{'\"#", "`{'\"#`"),
+            ("`'#", "\"`'#\""),
+            ("`\"#", "'`\"#'"),
+        ];
+        for (value, raw) in cases {
+            let mut env = parse_dialect("", Dialect::Node).unwrap();
+            env.set_value("A", value).unwrap();
+            assert_eq!(env.to_string(), format!("A={raw}\n"));
+            let reparsed = parse_dialect(&env.to_string(), Dialect::Node).unwrap();
+            assert_eq!(reparsed.get_value("A"), Some(value));
+        }
+        // with every kind of quote in it nothing reads back, the file stays as it is
+        let mut env = parse_dialect("A=1\n", Dialect::Node).unwrap();
+        assert_eq!(
+            env.set_value("A", "a'b\"c`d#"),
+            Err(KeyError::UnquotableValue("A".to_string()))
+        );
+        assert_eq!(
+            env.set_value("B", "a'b\"c`d#"),
+            Err(KeyError::UnquotableValue("B".to_string()))
+        );
+        assert_eq!(env.to_string(), "A=1\n");
+
+        let mut env = parse_dialect("", Dialect::Bash).unwrap();
+        env.set_value("A", "hello world").unwrap();
+        env.set_value("B", "$HOME").unwrap();
+        assert_eq!(env.to_string(), "A=\"hello world\"\nB=\"\\$HOME\"\n");
+
+        let mut env = parse_dialect("", Dialect::PythonDotenv).unwrap();
+        env.set_value("A", "$HOME 'a' \"b\" \\n").unwrap();
+        let reparsed = parse_dialect(&env.to_string(), Dialect::PythonDotenv).unwrap();
+        let expanded = reparsed.get_expanded("A", &Default::default()).unwrap();
+        assert_eq!(expanded.as_deref(), Some("$HOME 'a' \"b\" \\n"));
+    }
 }
@@ -0,0 +1,231 @@
+use std::fmt;
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+#[non_exhaustive]
+pub enum ParseErrorKind {
+    /// a line before the first key that is neither a comment nor blank
+    InvalidLineBeforeFirstKey,
+    /// a line that is neither `KEY=VALUE`, a comment, nor blank
+    InvalidLine,
+    /// a `=VALUE` line without a key
+    MissingKey,
+    /// a key name the `KeySyntax` doesn't accept
+    InvalidKey,
+    /// whitespace around `=` where a shell doesn't allow it
+    SpaceAroundEquals,
+    /// whitespace or shell syntax in an unquoted value where a shell reads it
+    ShellSyntax,
+    /// a quoted value without its closing quote
+    UnterminatedQuote,
+    /// text after the closing quote that isn't a comment
+    TrailingText,
+}
+
+impl fmt::Display for ParseErrorKind {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let message = match self {
+            ParseErrorKind::InvalidLineBeforeFirstKey => {
+                "Invalid line before first key (must be comment or blank)"
+            }
+            ParseErrorKind::InvalidLine => "Invalid line (must be KEY=VALUE, comment, or blank)",
+            ParseErrorKind::MissingKey => "Missing key before '='",
+            ParseErrorKind::InvalidKey => "Invalid key name",
+            ParseErrorKind::SpaceAroundEquals => "Whitespace around '='",
+            ParseErrorKind::ShellSyntax => "Unquoted shell syntax in value (quote the value)",
+            ParseErrorKind::UnterminatedQuote => "Unterminated quoted value",
+            ParseErrorKind::TrailingText => "Unexpected text after quoted value",
+        };
+        write!(f, "{}", message)
+    }
+}
+
+/// An error in the syntax of an env file, pointing at where it happened.
+#[derive(Debug, Clone, PartialEq, Eq)]
+#[non_exhaustive]
+pub struct ParseError {
+    pub kind: ParseErrorKind,
+    /// 1-based line number
+    pub line: usize,
+    /// 1-based column, counted in characters
+    pub column: usize,
+    /// the offending line
+    pub text: String,
+}
+
+impl ParseError {
+    // `offset` is a byte offset into `source`, which starts at line `line`
+    // and may span several lines when it holds a multiline value
+    pub(crate) fn new(kind: ParseErrorKind, line: usize, source: &str, offset: usize) -> Self {
+        let before = &source[..offset];
+        let line_start = before.rfind('\n').map_or(0, |pos| pos + 1);
+        ParseError {
+            kind,
+            line: line + before.matches('\n').count(),
+            column: source[line_start..offset].chars().count() + 1,
+            text: source[line_start..]
+                .lines()
+                .next()
+                .unwrap_or_default()
+                .to_string(),
+        }
+    }
+
+    /// Renders the error the way compilers do, with a caret under the problem.
+    pub fn diagnostic(&self, path: &str) -> String {
+        let number = self.line.to_string();
+        let gutter = " ".repeat(number.len());
+        // keep tabs so the caret lines up with the text above it
+        let indent: String = self
+            .text
+            .chars()
+            .take(self.column - 1)
+            .map(|c| if c == '\t' { '\t' } else { ' ' })
+            .collect();
+
+        format!(
+            "{}\n{}--> {}:{}:{}\n{} |\n{} | {}\n{} | {}^",
+            self.kind,
+            gutter,
+            path,
+            self.line,
+            self.column,
+            gutter,
+            number,
+            self.text,
+            gutter,
+            indent
+        )
+    }
+}
+
+impl fmt::Display for ParseError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(
+            f,
+            "{} at line {}, column {}: {}",
+            self.kind, self.line, self.column, self.text
+        )
+    }
+}
+
+impl std::error::Error for ParseError {}
+
+/// An edit that can't be made because of the keys in the file or the value
+/// to write.
+#[derive(Debug, Clone, PartialEq, Eq)]
+#[non_exhaustive]
+pub enum KeyError {
+    /// the key isn't defined
+    NotFound(String),
+    /// the key is defined already
+    AlreadyExists(String),
+    /// no comment introduces a section with this name
+    SectionNotFound(String),
+    /// the key name isn't accepted by the file's `KeySyntax`
+    InvalidKey(String),
+    /// no quoting of the dialect reads back as the new value of this key
+    UnquotableValue(String),
+}
+
+impl fmt::Display for KeyError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            KeyError::NotFound(key) => write!(f, "Key not found: {}", key),
+            KeyError::AlreadyExists(key) => write!(f, "Key already exists: {}", key),
+            KeyError::SectionNotFound(name) => write!(f, "Section not found: {}", name),
+            KeyError::InvalidKey(key) => write!(f, "Invalid key name: {}", key),
+            KeyError::UnquotableValue(key) => {
+                write!(f, "The value of {} can't be quoted for the dialect", key)
+            }
+        }
+    }
+}
+
+impl std::error::Error for KeyError {}
+
+/// A reference that can't be expanded.
+#[derive(Debug, Clone, PartialEq, Eq)]
+#[non_exhaustive]
+pub enum ExpandError {
+    /// `${VAR:?message}` or `${VAR?message}` of an unset variable, with the
+    /// expanded message if there is one
+    RequiredVariable {
+        name: String,
+        message: Option<String>,
+    },
+    /// `${...}` with a name or operator that isn't understood
+    InvalidReference(String),
+    /// `${` without its closing `}`, with the text that follows it
+    UnterminatedReference(String),
+}
+
+impl fmt::Display for ExpandError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            ExpandError::RequiredVariable {
+                name,
+                message: None,
+            } => write!(f, "Required variable {} is not set", name),
+            ExpandError::RequiredVariable {
+                name,
+                message: Some(message),
+            } => write!(f, "Required variable {} is not set: {}", name, message),
+            ExpandError::InvalidReference(inner) => write!(f, "Invalid reference: ${{{}}}", inner),
+            ExpandError::UnterminatedReference(text) => {
+                write!(f, "Unterminated reference: {}", text)
+            }
+        }
+    }
+}
+
+impl std::error::Error for ExpandError {}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_position_in_multiline_source() {
+        let source = "KEY=\"line1\nline2\" trailing";
+        let offset = source.find("trailing").unwrap();
+        let error = ParseError::new(ParseErrorKind::TrailingText, 4, source, offset);
+        assert_eq!(error.line, 5);
+        assert_eq!(error.column, 8);
+        assert_eq!(error.text, "line2\" trailing");
+    }
+
+    #[test]
+    fn test_column_counts_characters() {
+        let source = "KÉY=\"oops";
+        let error = ParseError::new(ParseErrorKind::UnterminatedQuote, 1, source, 5);
+        assert_eq!(error.column, 5);
+    }
+
+    #[test]
+    fn test_diagnostic() {
+        let error = ParseError {
+            kind: ParseErrorKind::UnterminatedQuote,
+            line: 12,
+            column: 5,
+            text: "KEY=\"value".to_string(),
+        };
+        assert_eq!(
+            error.diagnostic(".env"),
+            "Unterminated quoted value\n  --> .env:12:5\n   |\n12 | KEY=\"value\n   |     ^"
+        );
+    }
+
+    #[test]
+    fn test_display() {
+        let error = ParseError {
+            kind: ParseErrorKind::InvalidLine,
+            line: 2,
+            column: 1,
+            text: "foo".to_string(),
+        };
+        assert_eq!(
+            error.to_string(),
+            "Invalid line (must be KEY=VALUE, comment, or blank) at line 2, column 1: foo"
+        );
+    }
+}
@@ -0,0 +1,433 @@
+use crate::dialect::{Dialect, Escape, References};
+use crate::env_file::{DuplicatePolicy, Entry, EnvFile, Quote};
+use crate::error::ExpandError;
+
+type Result<T> = std::result::Result<T, ExpandError>;
+
+#[derive(Debug, Clone, Copy, Default)]
+#[non_exhaustive]
+pub struct ExpandOptions {
+    /// resolve references that aren't defined in the file from the process environment
+    pub env: bool,
+    /// which definition `get_expanded` returns for a duplicated key
+    pub duplicates: DuplicatePolicy,
+}
+
+impl EnvFile {
+    /// Returns every key with its `${VAR}` and `$VAR` references resolved, in file order.
+    ///
+    /// References are resolved the way the dialect of the file does, envq's own
+    /// dialect resolves them like docker compose.
+    pub fn expand(&self, options: &ExpandOptions) -> Result<Vec<(&str, String)>> {
+        let mut expander = Expander::new(self, options);
+        (0..expander.definitions.len())
+            .map(|index| {
+                let value = expander.resolve(index)?;
+                Ok((expander.definitions[index].key, value))
+            })
+            .collect()
+    }
+
+    /// Returns the value of `key` with its references resolved.
+    pub fn get_expanded(&self, key: &str, options: &ExpandOptions) -> Result<Option<String>> {
+        let mut expander = Expander::new(self, options);
+        let index = match options.duplicates {
+            DuplicatePolicy::First => expander.definitions.iter().position(|d| d.key == key),
+            DuplicatePolicy::Last => expander.definitions.iter().rposition(|d| d.key == key),
+        };
+        match index {
+            Some(index) => expander.resolve(index).map(Some),
+            None => Ok(None),
+        }
+    }
+}
+
+struct Definition<'a> {
+    key: &'a str,
+    value: &'a str,
+    raw: &'a str,
+    quote: Quote,
+}
+
+struct Expander<'a> {
+    definitions: Vec<Definition<'a>>,
+    dialect: Dialect,
+    options: ExpandOptions,
+    resolved: Vec<Option<String>>,
+}
+
+impl<'a> Expander<'a> {
+    fn new(env_file: &'a EnvFile, options: &ExpandOptions) -> Self {
+        let definitions: Vec<Definition> = env_file
+            .entries()
+            .iter()
+            .filter_map(|entry| match entry {
+                Entry::KeyValue {
+                    key,
+                    value,
+                    raw,
+                    quote,
+                    ..
+                } => Some(Definition {
+                    key,
+                    value,
+                    raw,
+                    quote: *quote,
+                }),
+                _ => None,
+            })
+            .collect();
+
+        Expander {
+            resolved: vec![None; definitions.len()],
+            definitions,
+            dialect: env_file.dialect(),
+            options: *options,
+        }
+    }
+
+    fn resolve(&mut self, index: usize) -> Result<String> {
+        if let Some(value) = &self.resolved[index] {
+            return Ok(value.clone());
+        }
+
+        let definition = &self.definitions[index];
+        let (quote, raw) = (definition.quote, definition.raw);
+        let value = match self.dialect.references(quote) {
+            References::None => definition.value.to_string(),
+            // the decoded value has no escapes left, like unquoted text
+            References::Braced => self.expand_text(definition.value, Quote::None, index)?,
+            References::Shell => {
+                let delimiter = quote.as_str().len();
+                self.expand_text(&raw[delimiter..raw.len() - delimiter], quote, index)?
+            }
+        };
+
+        self.resolved[index] = Some(value.clone());
+        Ok(value)
+    }
+
+    // a reference resolves to the closest definition before the referencing key,
+    // then to the process environment, the way loaders read a file top to bottom;
+    // only looking back also means references can't form a cycle
+    fn lookup(&mut self, name: &str, index: usize) -> Result<Option<String>> {
+        let earlier = self.definitions[..index]
+            .iter()
+            .rposition(|d| d.key == name);
+        if let Some(i) = earlier {
+            return self.resolve(i).map(Some);
+        }
+
+        if self.options.env {
+            return Ok(std::env::var(name).ok());
+        }
+
+        Ok(None)
+    }
+
+    fn expand_text(&mut self, text: &str, quote: Quote, index: usize) -> Result<String> {
+        let references = self.dialect.references(quote);
+        let mut value = String::with_capacity(text.len());
+        let mut chars = text.char_indices().peekable();
+
+        while let Some((pos, c)) = chars.next() {
+            match c {
+                '\\' => match chars
+                    .peek()
+                    .map(|&(_, next)| self.dialect.escape(quote, next))
+                {
+                    Some(Escape::Char(c)) => {
+                        value.push(c);
+                        chars.next();
+                    }
+                    Some(Escape::LineContinuation) => {
+                        chars.next();
+                    }
+                    Some(Escape::Literal) | None => value.push('\\'),
+                },
+                '$' => match chars.peek() {
+                    // docker compose writes a literal `$` as `$$`
+                    Some((_, '$')) if self.dialect == Dialect::Compose => {
+                        value.push('$');
+                        chars.next();
+                    }
+                    Some((_, '{')) => {
+                        // ${VAR} and ${VAR<op>word}
+                        let start = pos + 2;
+                        let end = find_closing_brace(text, start).ok_or_else(|| {
+                            ExpandError::UnterminatedReference(text[pos..].to_string())
+                        })?;
+                        let expanded = self.expand_braced(&text[start..end], quote, index)?;
+                        value.push_str(&expanded);
+                        while chars.next_if(|&(i, _)| i <= end).is_some() {}
+                    }
+                    Some(&(start, c))
+                        if (c == '_' || c.is_ascii_alphabetic())
+                            && references == References::Shell =>
+                    {
+                        // $VAR
+                        let mut end = start;
+                        while let Some((i, c)) =
+                            chars.next_if(|&(_, c)| c == '_' || c.is_ascii_alphanumeric())
+                        {
+                            end = i + c.len_utf8();
+                        }
+                        value.push_str(&self.lookup(&text[start..end], index)?.unwrap_or_default());
+                    }
+                    _ => value.push('$'),
+                },
+                _ => value.push(c),
+            }
+        }
+
+        Ok(value)
+    }
+
+    // expands the contents of `${...}`, with the operators docker compose supports:
+    // `:-` / `-` default, `:?` / `?` error and `:+` / `+` alternative value,
+    // where the colon forms also treat an empty value as unset
+    fn expand_braced(&mut self, inner: &str, quote: Quote, index: usize) -> Result<String> {
+        if self.dialect.references(quote) == References::Braced {
+            return self.expand_default(inner, index);
+        }
+
+        let name_len = inner
+            .find(|c: char| c != '_' && !c.is_ascii_alphanumeric())
+            .unwrap_or(inner.len());
+        let (name, rest) = inner.split_at(name_len);
+        if !is_name(name) {
+            return Err(ExpandError::InvalidReference(inner.to_string()));
+        }
+
+        let value = self.lookup(name, index)?;
+        if rest.is_empty() {
+            return Ok(value.unwrap_or_default());
+        }
+
+        let (colon, rest) = match rest.strip_prefix(':') {
+            Some(rest) => (true, rest),
+            None => (false, rest),
+        };
+        let mut op = rest.chars();
+        let (op, word) = (op.next(), op.as_str());
+
+        // with a colon an empty value counts as unset
+        let value = value.filter(|v| !(colon && v.is_empty()));
+
+        match (op, value) {
+            (Some('-'), Some(value)) => Ok(value),
+            (Some('-'), None) => self.expand_text(word, quote, index),
+            (Some('?'), Some(value)) => Ok(value),
+            (Some('?'), None) => {
+                let message = self.expand_text(word, quote, index)?;
+                Err(ExpandError::RequiredVariable {
+                    name: name.to_string(),
+                    message: Some(message).filter(|message| !message.is_empty()),
+                })
+            }
+            (Some('+'), Some(_)) => self.expand_text(word, quote, index),
+            (Some('+'), None) => Ok(String::new()),
+            _ => Err(ExpandError::InvalidReference(inner.to_string())),
+        }
+    }
+
+    // python-dotenv only knows `${VAR}` and `${VAR:-default}`, takes the default
+    // as it is and keeps empty values, anything else isn't a reference
+    fn expand_default(&mut self, inner: &str, index: usize) -> Result<String> {
+        let (name, default) = match inner.split_once(':') {
+            Some((name, rest)) => match rest.strip_prefix('-') {
+                Some(default) => (name, default),
+                None => return Ok(format!("${{{}}}", inner)),
+            },
+            None => (inner, ""),
+        };
+        Ok(self
+            .lookup(name, index)?
+            .unwrap_or_else(|| default.to_string()))
+    }
+}
+
+// finds the `}` matching an already opened `${`, allowing nested braces
+pub(crate) fn find_closing_brace(text: &str, start: usize) -> Option<usize> {
+    let mut depth = 0;
+    for (i, c) in text[start..].char_indices() {
+        match c {
+            '{' => depth += 1,
+            '}' if depth == 0 => return Some(start + i),
+            '}' => depth -= 1,
+            _ => {}
+        }
+    }
+    None
+}
+
+pub(crate) fn is_name(name: &str) -> bool {
+    let mut chars = name.chars();
+    chars
+        .next()
+        .is_some_and(|c| c == '_' || c.is_ascii_alphabetic())
+        && chars.all(|c| c == '_' || c.is_ascii_alphanumeric())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn expand(content: &str) -> Result<Vec<(String, String)>> {
+        let env = EnvFile::parse(content).unwrap();
+        let values = env.expand(&ExpandOptions::default())?;
+        Ok(values
+            .into_iter()
+            .map(|(k, v)| (k.to_string(), v))
+            .collect())
+    }
+
+    fn get(content: &str, key: &str) -> Option<String> {
+        let env = EnvFile::parse(content).unwrap();
+        env.get_expanded(key, &ExpandOptions::default()).unwrap()
+    }
+
+    #[test]
+    fn test_expand_braced_and_plain_references() {
+        let content =
+            "DB_USER=admin\nDB_HOST=localhost\nDATABASE_URL=postgres://${DB_USER}@$DB_HOST/app\n";
+        assert_eq!(
+            get(content, "DATABASE_URL"),
+            Some("postgres://admin@localhost/app".to_string())
+        );
+    }
+
+    #[test]
+    fn test_expand_in_double_quotes() {
+        let content = "NAME=world\nGREETING=\"hello ${NAME}\\n\\$NAME\"\n";
+        assert_eq!(
+            get(content, "GREETING"),
+            Some("hello world\n$NAME".to_string())
+        );
+    }
+
+    #[test]
+    fn test_single_quoted_values_are_not_expanded() {
+        let content = "NAME=world\nGREETING='hello ${NAME}'\n";
+        assert_eq!(get(content, "GREETING"), Some("hello ${NAME}".to_string()));
+    }
+
+    #[test]
+    fn test_expand_undefined_is_empty() {
+        let content = "URL=http://${HOST_THAT_IS_NOT_SET}/x\n";
+        assert_eq!(get(content, "URL"), Some("http:///x".to_string()));
+    }
+
+    #[test]
+    fn test_expand_uses_closest_earlier_definition() {
+        let content = "A=1\nB=$A\nA=2\nC=$A\n";
+        assert_eq!(
+            expand(content).unwrap(),
+            vec![
+                ("A".to_string(), "1".to_string()),
+                ("B".to_string(), "1".to_string()),
+                ("A".to_string(), "2".to_string()),
+                ("C".to_string(), "2".to_string()),
+            ]
+        );
+    }
+
+    #[test]
+    fn test_expand_ignores_later_definitions() {
+        let content = "URL=http://$HOST\nHOST=localhost\n";
+        assert_eq!(get(content, "URL"), Some("http://".to_string()));
+
+        // falls back to the process environment instead
+        let env = EnvFile::parse("DIRS=$PATH\nPATH=/file\n").unwrap();
+        let options = ExpandOptions {
+            env: true,
+            ..Default::default()
+        };
+        let value = env.get_expanded("DIRS", &options).unwrap();
+        assert_eq!(value, std::env::var("PATH").ok());
+    }
+
+    #[test]
+    fn test_dollar_without_name_is_literal() {
+        let content = "PRICE=5$\nOTHER=\"$ 1\"\n";
+        assert_eq!(get(content, "PRICE"), Some("5$".to_string()));
+        assert_eq!(get(content, "OTHER"), Some("$ 1".to_string()));
+    }
+
+    #[test]
+    fn test_default_operators() {
+        let content = "EMPTY=\nSET=value\nA=${UNSET:-default}\nB=${EMPTY:-default}\nC=${UNSET-default}\nD=${EMPTY-default}\nE=${SET:-default}\n";
+        assert_eq!(get(content, "A"), Some("default".to_string()));
+        assert_eq!(get(content, "B"), Some("default".to_string()));
+        assert_eq!(get(content, "C"), Some("default".to_string()));
+        assert_eq!(get(content, "D"), Some("".to_string()));
+        assert_eq!(get(content, "E"), Some("value".to_string()));
+    }
+
+    #[test]
+    fn test_alternative_operators() {
+        let content =
+            "EMPTY=\nSET=value\nA=${SET:+alt}\nB=${EMPTY:+alt}\nC=${EMPTY+alt}\nD=${UNSET+alt}\n";
+        assert_eq!(get(content, "A"), Some("alt".to_string()));
+        assert_eq!(get(content, "B"), Some("".to_string()));
+        assert_eq!(get(content, "C"), Some("alt".to_string()));
+        assert_eq!(get(content, "D"), Some("".to_string()));
+    }
+
+    #[test]
+    fn test_error_operators() {
+        let content = "EMPTY=\nSET=value\nA=${SET:?missing}\nB=${EMPTY:?needs a value}\nC=${EMPTY?missing}\nD=${UNSET?}\n";
+        let env = EnvFile::parse(content).unwrap();
+        let options = ExpandOptions::default();
+        assert_eq!(
+            env.get_expanded("A", &options).unwrap(),
+            Some("value".to_string())
+        );
+        assert_eq!(
+            env.get_expanded("B", &options).unwrap_err().to_string(),
+            "Required variable EMPTY is not set: needs a value"
+        );
+        assert_eq!(
+            env.get_expanded("C", &options).unwrap(),
+            Some("".to_string())
+        );
+        assert_eq!(
+            env.get_expanded("D", &options).unwrap_err().to_string(),
+            "Required variable UNSET is not set"
+        );
+    }
+
+    #[test]
+    fn test_nested_default_is_expanded_lazily() {
+        let content = "HOST=localhost\nURL=\"http://${API_HOST:-${HOST}}:${PORT:-8080}\"\nOK=${HOST:-${MISSING:?not needed}}\n";
+        assert_eq!(
+            get(content, "URL"),
+            Some("http://localhost:8080".to_string())
+        );
+        assert_eq!(get(content, "OK"), Some("localhost".to_string()));
+    }
+
+    #[test]
+    fn test_invalid_operator_errors() {
+        assert!(expand("A=${B:x}\n").is_err());
+        assert!(expand("A=${1B}\n").is_err());
+    }
+
+    #[test]
+    fn test_mutual_references_resolve_top_down() {
+        let content = "A=${B}x\nB=${A}y\n";
+        assert_eq!(
+            expand(content).unwrap(),
+            vec![
+                ("A".to_string(), "x".to_string()),
+                ("B".to_string(), "xy".to_string()),
+            ]
+        );
+    }
+
+    #[test]
+    fn test_unterminated_reference_errors() {
+        let content = "A=${B\n";
+        assert!(expand(content).is_err());
+    }
+}
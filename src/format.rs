@@ -0,0 +1,259 @@
+use crate::dialect::Dialect;
+use crate::env_file::{Entry, EnvFile, Quote, can_be_unquoted};
+
+/// How `EnvFile::format` quotes values.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub enum QuoteStyle {
+    /// keep the quotes as they are
+    Preserve,
+    /// quote only values that need it
+    #[default]
+    Minimal,
+    /// double quote every value
+    Double,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+#[non_exhaustive]
+pub struct FormatOptions {
+    pub quote_style: QuoteStyle,
+    /// line up the inline comments of consecutive keys
+    pub align_comments: bool,
+    /// replace runs of blank lines with a single one
+    pub collapse_blank_lines: bool,
+    pub uppercase_keys: bool,
+}
+
+impl EnvFile {
+    /// Rewrites the file in a canonical style: no indentation, no spaces
+    /// around `=`, one space before and after the `#` of inline comments,
+    /// no trailing whitespace and a final newline. Values keep their meaning,
+    /// invalid lines are left alone. Quotes are only changed in envq's own
+    /// dialect, loaders disagree too much on what they mean.
+    pub fn format(&mut self, options: &FormatOptions) {
+        let options = &FormatOptions {
+            quote_style: match self.dialect() {
+                Dialect::Envq => options.quote_style,
+                _ => QuoteStyle::Preserve,
+            },
+            ..*options
+        };
+        let mut entries = self.entries().to_vec();
+        for entry in &mut entries {
+            format_entry(entry, options);
+        }
+
+        if options.collapse_blank_lines {
+            let mut previous_blank = true;
+            entries.retain(|entry| {
+                let blank = matches!(entry, Entry::Blank(_));
+                let keep = !(blank && previous_blank);
+                previous_blank = blank;
+                keep
+            });
+            if let Some(Entry::Blank(_)) = entries.last() {
+                entries.pop();
+            }
+        }
+
+        if options.align_comments {
+            for group in entries.split_mut(|entry| matches!(entry, Entry::Blank(_))) {
+                align_comments(group);
+            }
+        }
+
+        self.set_entries(entries);
+        let mut serialize_options = *self.serialize_options();
+        serialize_options.final_newline = true;
+        self.set_serialize_options(serialize_options);
+    }
+}
+
+fn format_entry(entry: &mut Entry, options: &FormatOptions) {
+    match entry {
+        Entry::KeyValue {
+            key,
+            quote,
+            raw,
+            comment,
+            trivia,
+            ..
+        } => {
+            if options.uppercase_keys {
+                *key = key.to_uppercase();
+            }
+            (*quote, *raw) = requote(*quote, raw, options.quote_style);
+
+            trivia.indent.clear();
+            trivia.export_gap = " ".to_string();
+            trivia.before_equals.clear();
+            trivia.after_equals.clear();
+            trivia.before_comment = " ".to_string();
+            trivia.comment_gap = " ".to_string();
+            // this drops a dangling `#` without a comment as well
+            trivia.trailing.clear();
+            if let Some(text) = comment {
+                *text = text.trim_end().to_string();
+            }
+        }
+        Entry::Comment(text) => *text = text.trim().to_string(),
+        Entry::Blank(text) => text.clear(),
+        Entry::Invalid(_) => {}
+    }
+}
+
+// changes the quotes of a value without changing what it means,
+// unquoted and double quoted values expand references, single quoted don't
+fn requote(quote: Quote, raw: &str, style: QuoteStyle) -> (Quote, String) {
+    let delimiter = quote.as_str().len();
+    let inner = &raw[delimiter..raw.len() - delimiter];
+    match (style, quote) {
+        (QuoteStyle::Minimal, Quote::Single) if !inner.contains('$') && can_be_unquoted(inner) => {
+            (Quote::None, inner.to_string())
+        }
+        (QuoteStyle::Minimal, Quote::Double) if !inner.contains('\\') && can_be_unquoted(inner) => {
+            (Quote::None, inner.to_string())
+        }
+        (QuoteStyle::Double, Quote::None) => {
+            let escaped = inner.replace('\\', "\\\\").replace('"', "\\\"");
+            (Quote::Double, format!("\"{}\"", escaped))
+        }
+        (QuoteStyle::Double, Quote::Single) => (
+            Quote::Double,
+            Dialect::Envq.quote_value(inner, Quote::Double),
+        ),
+        _ => (quote, raw.to_string()),
+    }
+}
+
+// pads single line keys so their `#` ends up in the same column
+fn align_comments(group: &mut [Entry]) {
+    // the entries are formatted already, so the text before `#` is `export KEY=raw`
+    let width = |entry: &Entry| match entry {
+        Entry::KeyValue {
+            export,
+            key,
+            raw,
+            comment: Some(_),
+            ..
+        } if !raw.contains('\n') => {
+            let export_len = if *export { "export ".len() } else { 0 };
+            Some(export_len + key.chars().count() + 1 + raw.chars().count())
+        }
+        _ => None,
+    };
+
+    let Some(column) = group.iter().filter_map(width).max() else {
+        return;
+    };
+    for entry in group.iter_mut() {
+        if let Some(width) = width(entry)
+            && let Entry::KeyValue { trivia, .. } = entry
+        {
+            trivia.before_comment = " ".repeat(column - width + 1);
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn format(content: &str, options: FormatOptions) -> String {
+        let mut env = EnvFile::parse(content).unwrap();
+        env.format(&options);
+        env.to_string()
+    }
+
+    #[test]
+    fn test_format_spacing() {
+        let content = "  export   KEY = value   #comment  \n\tOTHER= x #\n  # note  \n \nLAST=1";
+        assert_eq!(
+            format(content, FormatOptions::default()),
+            "export KEY=value # comment\nOTHER=x\n# note\n\nLAST=1\n"
+        );
+    }
+
+    #[test]
+    fn test_minimal_quotes() {
+        let content = "A=\"plain\"\nB='plain'\nC='$HOME'\nD=\"$HOME\"\nE=\"a\\nb\"\nF=' padded '\nG=\"a #b\"\nH=\"foo bar\"\nI=\"\"\nJ=''\n";
+        assert_eq!(
+            format(content, FormatOptions::default()),
+            "A=plain\nB=plain\nC='$HOME'\nD=$HOME\nE=\"a\\nb\"\nF=' padded '\nG=\"a #b\"\nH=\"foo bar\"\nI=\"\"\nJ=''\n"
+        );
+    }
+
+    #[test]
+    fn test_double_quotes_keep_meaning() {
+        let options = FormatOptions {
+            quote_style: QuoteStyle::Double,
+            ..FormatOptions::default()
+        };
+        let content = "A=plain\nB='$HOME'\nC=$HOME\nD=C:\\dir\n";
+        let formatted = format(content, options);
+        assert_eq!(
+            formatted,
+            "A=\"plain\"\nB=\"\\$HOME\"\nC=\"$HOME\"\nD=\"C:\\\\dir\"\n"
+        );
+        let env = EnvFile::parse(&formatted).unwrap();
+        assert_eq!(env.get_value("B"), Some("$HOME"));
+        assert_eq!(env.get_value("D"), Some("C:\\dir"));
+    }
+
+    #[test]
+    fn test_preserve_quotes() {
+        let options = FormatOptions {
+            quote_style: QuoteStyle::Preserve,
+            ..FormatOptions::default()
+        };
+        assert_eq!(format("A = 'x'\n", options), "A='x'\n");
+    }
+
+    #[test]
+    fn test_align_comments() {
+        let options = FormatOptions {
+            align_comments: true,
+            ..FormatOptions::default()
+        };
+        let content = "A=1 # one\nLONGER=22 # two\nNONE=x\n\nB=1 # three\n";
+        assert_eq!(
+            format(content, options),
+            "A=1       # one\nLONGER=22 # two\nNONE=x\n\nB=1 # three\n"
+        );
+    }
+
+    #[test]
+    fn test_collapse_blank_lines() {
+        let options = FormatOptions {
+            collapse_blank_lines: true,
+            ..FormatOptions::default()
+        };
+        assert_eq!(
+            format("\n\n# header\n\n\n\nA=1\n\n\n", options),
+            "# header\n\nA=1\n"
+        );
+    }
+
+    #[test]
+    fn test_uppercase_keys() {
+        let options = FormatOptions {
+            uppercase_keys: true,
+            ..FormatOptions::default()
+        };
+        let mut env = EnvFile::parse("db_host=x\n").unwrap();
+        env.format(&options);
+        assert_eq!(env.to_string(), "DB_HOST=x\n");
+        assert_eq!(env.get_value("DB_HOST"), Some("x"));
+    }
+
+    #[test]
+    fn test_format_is_idempotent() {
+        let options = FormatOptions {
+            align_comments: true,
+            collapse_blank_lines: true,
+            ..FormatOptions::default()
+        };
+        let once = format(" A = 'x' #a\n\n\nBB=\"y z\"   # b\n", options);
+        assert_eq!(format(&once, options), once);
+    }
+}
@@ -0,0 +1,43 @@
+//! Query and edit `.env` files without losing their formatting.
+//!
+//! ```
+//! use envq::EnvFile;
+//!
+//! let mut env = EnvFile::parse("# config\nHOST=localhost # the host\n").unwrap();
+//! assert_eq!(env.get_value("HOST"), Some("localhost"));
+//!
+//! env.set_value("HOST", "example.com").unwrap();
+//! env.set_value("PORT", "8080").unwrap();
+//! assert_eq!(
+//!     env.to_string(),
+//!     "# config\nHOST=example.com # the host\nPORT=8080\n"
+//! );
+//! ```
+
+mod convert;
+#[cfg(feature = "serde")]
+mod de;
+mod dialect;
+mod entry;
+mod env_file;
+mod error;
+mod expand;
+mod format;
+mod lint;
+#[cfg(feature = "serde")]
+mod ser;
+mod sort;
+
+pub use convert::{FromEnvValue, Url, ValueError};
+#[cfg(feature = "serde")]
+pub use de::{DeserializeError, from_env_file, from_str};
+pub use dialect::Dialect;
+pub use entry::{KeyEntry, KeyMut, KeyRef, OccupiedKey, VacantKey};
+pub use env_file::{
+    DuplicatePolicy, Entry, EnvFile, KeySyntax, LineEnding, ParseOptions, Position, Quote,
+    SerializeOptions, Trivia,
+};
+pub use error::{ExpandError, KeyError, ParseError, ParseErrorKind};
+pub use expand::ExpandOptions;
+pub use format::{FormatOptions, QuoteStyle};
+pub use lint::{Diagnostic, Linter, Rule, Severity};
@@ -0,0 +1,642 @@
+use std::collections::{HashMap, HashSet};
+use std::fmt;
+
+use crate::dialect::{Dialect, Escape, References};
+use crate::env_file::{Entry, EnvFile, KeySyntax, Quote};
+use crate::expand::find_closing_brace;
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord)]
+pub enum Severity {
+    Warning,
+    Error,
+}
+
+impl fmt::Display for Severity {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Severity::Warning => write!(f, "warning"),
+            Severity::Error => write!(f, "error"),
+        }
+    }
+}
+
+/// A check that looks for one kind of problem in an env file.
+pub trait Rule {
+    /// A short kebab-case name, used to report and to suppress the rule.
+    fn id(&self) -> &'static str;
+    fn severity(&self) -> Severity;
+    /// Returns the index into [`EnvFile::entries`] and a message for every problem.
+    fn check(&self, env_file: &EnvFile) -> Vec<(usize, String)>;
+}
+
+/// A problem found by a [`Rule`].
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Diagnostic {
+    pub rule: &'static str,
+    pub severity: Severity,
+    /// 1-based line number
+    pub line: usize,
+    pub message: String,
+}
+
+impl fmt::Display for Diagnostic {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(
+            f,
+            "{}[{}] at line {}: {}",
+            self.severity, self.rule, self.line, self.message
+        )
+    }
+}
+
+/// Runs a set of rules over env files.
+///
+/// A line is skipped by all rules with a `# envq-ignore` comment on it or on
+/// the line above it, `# envq-ignore: empty-value, lowercase-key` only skips
+/// the listed rules.
+pub struct Linter {
+    rules: Vec<Box<dyn Rule>>,
+}
+
+impl Default for Linter {
+    /// A linter with all built-in rules.
+    fn default() -> Self {
+        Linter {
+            rules: vec![
+                Box::new(DuplicateKey),
+                Box::new(InvalidKey),
+                Box::new(LowercaseKey),
+                Box::new(UnquotedSpace),
+                Box::new(TrailingWhitespace),
+                Box::new(EmptyValue),
+                Box::new(UndefinedReference),
+                Box::new(UnescapedDollar),
+            ],
+        }
+    }
+}
+
+impl Linter {
+    /// A linter without any rules.
+    pub fn new() -> Self {
+        Linter { rules: Vec::new() }
+    }
+
+    pub fn with_rule(mut self, rule: impl Rule + 'static) -> Self {
+        self.rules.push(Box::new(rule));
+        self
+    }
+
+    /// Removes the rule with this id, returns whether there was one.
+    pub fn disable(&mut self, id: &str) -> bool {
+        let len = self.rules.len();
+        self.rules.retain(|rule| rule.id() != id);
+        self.rules.len() != len
+    }
+
+    pub fn rule_ids(&self) -> Vec<&'static str> {
+        self.rules.iter().map(|rule| rule.id()).collect()
+    }
+
+    /// Returns the problems of every rule, ordered by line.
+    pub fn lint(&self, env_file: &EnvFile) -> Vec<Diagnostic> {
+        let entries = env_file.entries();
+        let lines = env_file.entry_lines();
+
+        let mut diagnostics: Vec<Diagnostic> = self
+            .rules
+            .iter()
+            .flat_map(|rule| {
+                rule.check(env_file)
+                    .into_iter()
+                    .filter(|(index, _)| !is_ignored(entries, *index, rule.id()))
+                    .map(|(index, message)| Diagnostic {
+                        rule: rule.id(),
+                        severity: rule.severity(),
+                        line: lines[index],
+                        message,
+                    })
+            })
+            .collect();
+        diagnostics.sort_by_key(|diagnostic| diagnostic.line);
+        diagnostics
+    }
+}
+
+// `# envq-ignore` on the line itself or on the line above
+fn is_ignored(entries: &[Entry], index: usize, rule: &str) -> bool {
+    let inline = match &entries[index] {
+        Entry::KeyValue { comment, .. } => comment.as_deref(),
+        Entry::Comment(text) => Some(text.trim_start_matches('#')),
+        _ => None,
+    };
+    let above = match index.checked_sub(1).map(|above| &entries[above]) {
+        Some(Entry::Comment(text)) => Some(text.trim_start_matches('#')),
+        _ => None,
+    };
+    [inline, above]
+        .into_iter()
+        .flatten()
+        .any(|comment| ignores(comment, rule))
+}
+
+fn ignores(comment: &str, rule: &str) -> bool {
+    let Some(rest) = comment.trim().strip_prefix("envq-ignore") else {
+        return false;
+    };
+    match rest.trim_start().strip_prefix(':') {
+        Some(ids) => ids.split(',').any(|id| id.trim() == rule),
+        None => rest.is_empty() || rest.starts_with(char::is_whitespace),
+    }
+}
+
+// the key, quoting and raw text of every key, with its entry index
+fn key_values(env_file: &EnvFile) -> impl Iterator<Item = (usize, &str, Quote, &str)> {
+    env_file
+        .entries()
+        .iter()
+        .enumerate()
+        .filter_map(|(index, entry)| match entry {
+            Entry::KeyValue {
+                key, quote, raw, ..
+            } => Some((index, key.as_str(), *quote, raw.as_str())),
+            _ => None,
+        })
+}
+
+struct DuplicateKey;
+
+impl Rule for DuplicateKey {
+    fn id(&self) -> &'static str {
+        "duplicate-key"
+    }
+
+    fn severity(&self) -> Severity {
+        Severity::Warning
+    }
+
+    fn check(&self, env_file: &EnvFile) -> Vec<(usize, String)> {
+        let lines = env_file.entry_lines();
+        let mut first = HashMap::new();
+        key_values(env_file)
+            .filter_map(|(index, key, _, _)| match first.get(key) {
+                Some(&line) => Some((
+                    index,
+                    format!("{} is already defined on line {}", key, line),
+                )),
+                None => {
+                    first.insert(key, lines[index]);
+                    None
+                }
+            })
+            .collect()
+    }
+}
+
+struct InvalidKey;
+
+impl Rule for InvalidKey {
+    fn id(&self) -> &'static str {
+        "invalid-key"
+    }
+
+    fn severity(&self) -> Severity {
+        Severity::Error
+    }
+
+    // keys are checked against the key syntax of the file, envq's own dialect
+    // parses nearly anything but recommends names a shell accepts
+    fn check(&self, env_file: &EnvFile) -> Vec<(usize, String)> {
+        let key_syntax = match (env_file.dialect(), env_file.key_syntax()) {
+            (Dialect::Envq, KeySyntax::Permissive) => KeySyntax::Posix,
+            (_, key_syntax) => key_syntax,
+        };
+        let allowed = match key_syntax {
+            KeySyntax::Compose => "letters, digits, underscores, dots and dashes",
+            _ => "letters, digits and underscores",
+        };
+        key_values(env_file)
+            .filter(|(_, key, _, _)| !key_syntax.is_valid(key))
+            .map(|(index, key, _, _)| {
+                let message = format!(
+                    "{} is not a valid name, use {} and don't start with a digit",
+                    key, allowed
+                );
+                (index, message)
+            })
+            .collect()
+    }
+}
+
+struct LowercaseKey;
+
+impl Rule for LowercaseKey {
+    fn id(&self) -> &'static str {
+        "lowercase-key"
+    }
+
+    fn severity(&self) -> Severity {
+        Severity::Warning
+    }
+
+    fn check(&self, env_file: &EnvFile) -> Vec<(usize, String)> {
+        key_values(env_file)
+            .filter(|(_, key, _, _)| key.chars().any(char::is_lowercase))
+            .map(|(index, key, _, _)| {
+                let message = format!("{} should be uppercase: {}", key, key.to_uppercase());
+                (index, message)
+            })
+            .collect()
+    }
+}
+
+struct UnquotedSpace;
+
+impl Rule for UnquotedSpace {
+    fn id(&self) -> &'static str {
+        "unquoted-space"
+    }
+
+    fn severity(&self) -> Severity {
+        Severity::Warning
+    }
+
+    fn check(&self, env_file: &EnvFile) -> Vec<(usize, String)> {
+        key_values(env_file)
+            .filter(|(_, _, quote, raw)| *quote == Quote::None && raw.contains(char::is_whitespace))
+            .map(|(index, key, _, _)| {
+                let message = format!("the value of {} contains spaces and should be quoted", key);
+                (index, message)
+            })
+            .collect()
+    }
+}
+
+struct TrailingWhitespace;
+
+impl Rule for TrailingWhitespace {
+    fn id(&self) -> &'static str {
+        "trailing-whitespace"
+    }
+
+    fn severity(&self) -> Severity {
+        Severity::Warning
+    }
+
+    fn check(&self, env_file: &EnvFile) -> Vec<(usize, String)> {
+        env_file
+            .entries()
+            .iter()
+            .enumerate()
+            .filter(|(_, entry)| entry.to_string().ends_with(char::is_whitespace))
+            .map(|(index, _)| (index, "line ends with whitespace".to_string()))
+            .collect()
+    }
+}
+
+struct EmptyValue;
+
+impl Rule for EmptyValue {
+    fn id(&self) -> &'static str {
+        "empty-value"
+    }
+
+    fn severity(&self) -> Severity {
+        Severity::Warning
+    }
+
+    // `KEY=""` says the value is meant to be empty
+    fn check(&self, env_file: &EnvFile) -> Vec<(usize, String)> {
+        key_values(env_file)
+            .filter(|(_, _, _, raw)| raw.is_empty())
+            .map(|(index, key, _, _)| {
+                let message = format!(
+                    "{} has no value, use {}=\"\" if it is meant to be empty",
+                    key, key
+                );
+                (index, message)
+            })
+            .collect()
+    }
+}
+
+struct UndefinedReference;
+
+impl Rule for UndefinedReference {
+    fn id(&self) -> &'static str {
+        "undefined-reference"
+    }
+
+    fn severity(&self) -> Severity {
+        Severity::Warning
+    }
+
+    fn check(&self, env_file: &EnvFile) -> Vec<(usize, String)> {
+        let mut problems = Vec::new();
+        // references only resolve to keys defined above them
+        let mut defined = HashSet::new();
+        for (index, key, scan) in scans(env_file) {
+            for name in scan.names {
+                if !defined.contains(name) {
+                    let message =
+                        format!("{} references {}, which is not defined above it", key, name);
+                    problems.push((index, message));
+                }
+            }
+            defined.insert(key);
+        }
+        problems
+    }
+}
+
+struct UnescapedDollar;
+
+impl Rule for UnescapedDollar {
+    fn id(&self) -> &'static str {
+        "unescaped-dollar"
+    }
+
+    fn severity(&self) -> Severity {
+        Severity::Warning
+    }
+
+    fn check(&self, env_file: &EnvFile) -> Vec<(usize, String)> {
+        scans(env_file)
+            .filter(|(_, _, scan)| scan.lone_dollars > 0)
+            .map(|(index, key, _)| {
+                let message = format!(
+                    "the value of {} has a $ that doesn't start a reference, escape it or use single quotes",
+                    key
+                );
+                (index, message)
+            })
+            .collect()
+    }
+}
+
+// scans the value of every key, with its key and entry index
+fn scans(env_file: &EnvFile) -> impl Iterator<Item = (usize, &str, Scan<'_>)> {
+    let dialect = env_file.dialect();
+    env_file
+        .entries()
+        .iter()
+        .enumerate()
+        .filter_map(move |(index, entry)| match entry {
+            Entry::KeyValue {
+                key,
+                value,
+                quote,
+                raw,
+                ..
+            } => Some((
+                index,
+                key.as_str(),
+                Scan::value(dialect, *quote, raw, value),
+            )),
+            _ => None,
+        })
+}
+
+// finds references the way `expand` resolves them in the dialect of the file
+struct Scan<'a> {
+    dialect: Dialect,
+    quote: Quote,
+    references: References,
+    // references without a fallback value
+    names: Vec<&'a str>,
+    lone_dollars: usize,
+}
+
+impl<'a> Scan<'a> {
+    fn value(dialect: Dialect, quote: Quote, raw: &'a str, value: &'a str) -> Self {
+        let references = dialect.references(quote);
+        let mut scan = Scan {
+            dialect,
+            quote,
+            references,
+            names: Vec::new(),
+            lone_dollars: 0,
+        };
+        match references {
+            References::None => {}
+            // the decoded value has no escapes left, like unquoted text
+            References::Braced => {
+                scan.quote = Quote::None;
+                scan.text(value, false);
+            }
+            References::Shell => {
+                let delimiter = quote.as_str().len();
+                scan.text(&raw[delimiter..raw.len() - delimiter], false);
+            }
+        }
+        scan
+    }
+
+    fn text(&mut self, text: &'a str, has_fallback: bool) {
+        let mut chars = text.char_indices().peekable();
+        while let Some((pos, c)) = chars.next() {
+            match c {
+                '\\' if chars.peek().is_some_and(|&(_, next)| {
+                    self.dialect.escape(self.quote, next) != Escape::Literal
+                }) =>
+                {
+                    chars.next();
+                }
+                '$' => match chars.peek() {
+                    // docker compose writes a literal `$` as `$$`
+                    Some((_, '$')) if self.dialect == Dialect::Compose => {
+                        chars.next();
+                    }
+                    Some((_, '{')) => {
+                        let start = pos + 2;
+                        let Some(end) = find_closing_brace(text, start) else {
+                            return;
+                        };
+                        self.braced(&text[start..end], has_fallback);
+                        while chars.next_if(|&(i, _)| i <= end).is_some() {}
+                    }
+                    Some(&(start, c))
+                        if (c == '_' || c.is_ascii_alphabetic())
+                            && self.references == References::Shell =>
+                    {
+                        let mut end = start;
+                        while let Some((i, c)) =
+                            chars.next_if(|&(_, c)| c == '_' || c.is_ascii_alphanumeric())
+                        {
+                            end = i + c.len_utf8();
+                        }
+                        if !has_fallback {
+                            self.names.push(&text[start..end]);
+                        }
+                    }
+                    // python-dotenv keeps any other `$` as it is, there's nothing to escape
+                    _ if self.references == References::Braced => {}
+                    _ => self.lone_dollars += 1,
+                },
+                _ => {}
+            }
+        }
+    }
+
+    fn braced(&mut self, inner: &'a str, has_fallback: bool) {
+        if self.references == References::Braced {
+            // python-dotenv only knows `${VAR}` and `${VAR:-default}`
+            if !inner.contains(':') {
+                self.names.push(inner);
+            }
+            return;
+        }
+
+        let name_len = inner
+            .find(|c: char| c != '_' && !c.is_ascii_alphanumeric())
+            .unwrap_or(inner.len());
+        let (name, rest) = inner.split_at(name_len);
+        let rest = rest.strip_prefix(':').unwrap_or(rest);
+        // `-` and `+` give the reference a value when the variable is unset
+        let fallback = rest.starts_with(['-', '+']);
+        if !has_fallback && !fallback {
+            self.names.push(name);
+        }
+        if !rest.is_empty() {
+            self.text(&rest[1..], has_fallback || fallback);
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::env_file::ParseOptions;
+
+    fn lint(content: &str) -> Vec<(&'static str, usize)> {
+        let env = EnvFile::parse(content).unwrap();
+        Linter::default()
+            .lint(&env)
+            .into_iter()
+            .map(|diagnostic| (diagnostic.rule, diagnostic.line))
+            .collect()
+    }
+
+    #[test]
+    fn test_clean_file() {
+        let content = "# header\n\nHOST=localhost\nURL=\"http://${HOST}/\"\nEMPTY=\"\"\n";
+        assert_eq!(lint(content), vec![]);
+    }
+
+    #[test]
+    fn test_rules() {
+        let content =
+            "A=1\nA=2\ndb_host=x\nNAME=John Doe\nB=1 \nC=\nD=${MISSING}\nE=${OTHER:-x}\nF=pa$5\n";
+        assert_eq!(
+            lint(content),
+            vec![
+                ("duplicate-key", 2),
+                ("lowercase-key", 3),
+                ("unquoted-space", 4),
+                ("trailing-whitespace", 5),
+                ("empty-value", 6),
+                ("undefined-reference", 7),
+                ("unescaped-dollar", 9),
+            ]
+        );
+    }
+
+    #[test]
+    fn test_reference_to_later_key() {
+        let content = "URL=http://${HOST}\nHOST=localhost\nOTHER=${HOST}\n";
+        assert_eq!(lint(content), vec![("undefined-reference", 1)]);
+    }
+
+    #[test]
+    fn test_invalid_key() {
+        let env = EnvFile::parse("1FOO=x\nMY-KEY=y\n").unwrap();
+        let diagnostics = Linter::default().lint(&env);
+        assert_eq!(diagnostics.len(), 2);
+        assert!(diagnostics.iter().all(|d| d.rule == "invalid-key"));
+        assert_eq!(diagnostics[0].severity, Severity::Error);
+
+        // names the key syntax or dialect accepts are fine
+        let options = ParseOptions {
+            key_syntax: Some(KeySyntax::Compose),
+            ..Default::default()
+        };
+        let env = EnvFile::parse_with("APP.NAME=x\nMY-KEY=y\n", &options).unwrap();
+        assert_eq!(Linter::default().lint(&env), vec![]);
+        let options = ParseOptions {
+            dialect: Dialect::PythonDotenv,
+            ..Default::default()
+        };
+        let env = EnvFile::parse_with("APP.NAME=x\n", &options).unwrap();
+        assert_eq!(Linter::default().lint(&env), vec![]);
+    }
+
+    #[test]
+    fn test_single_quotes_and_escapes_are_not_references() {
+        assert_eq!(lint("A='$MISSING'\nB=\"\\$MISSING\"\n"), vec![]);
+    }
+
+    #[test]
+    fn test_references_follow_the_dialect() {
+        let lint = |dialect, content| {
+            let options = ParseOptions {
+                dialect,
+                ..Default::default()
+            };
+            let env = EnvFile::parse_with(content, &options).unwrap();
+            Linter::default()
+                .lint(&env)
+                .into_iter()
+                .map(|diagnostic| (diagnostic.rule, diagnostic.line))
+                .collect::<Vec<_>>()
+        };
+        // systemd and node never expand values
+        assert_eq!(lint(Dialect::Systemd, "A=$HOME\nB=a$\n"), vec![]);
+        assert_eq!(lint(Dialect::Node, "A=\"${HOME}\"\n"), vec![]);
+        // compose writes a literal `$` as `$$`
+        assert_eq!(
+            lint(Dialect::Compose, "A=$$HOME\nB=$\n"),
+            vec![("unescaped-dollar", 2)]
+        );
+        // python-dotenv only expands `${VAR}`, a bare `$` is kept as it is
+        assert_eq!(
+            lint(Dialect::PythonDotenv, "A=$HOME\nB=a$\nC=${D:-x}\nE=${F}\n"),
+            vec![("undefined-reference", 4)]
+        );
+    }
+
+    #[test]
+    fn test_ignore_comments() {
+        let content =
+            "C= # envq-ignore\n# envq-ignore: empty-value\nD=\nE= # envq-ignore: lowercase-key\n";
+        assert_eq!(lint(content), vec![("empty-value", 4)]);
+    }
+
+    #[test]
+    fn test_custom_rule() {
+        struct NoSecrets;
+        impl Rule for NoSecrets {
+            fn id(&self) -> &'static str {
+                "no-secrets"
+            }
+            fn severity(&self) -> Severity {
+                Severity::Error
+            }
+            fn check(&self, env_file: &EnvFile) -> Vec<(usize, String)> {
+                key_values(env_file)
+                    .filter(|(_, key, _, _)| key.contains("SECRET"))
+                    .map(|(index, _, _, _)| (index, "no secrets".to_string()))
+                    .collect()
+            }
+        }
+
+        let env = EnvFile::parse("A=1\nSECRET=x\n").unwrap();
+        let mut linter = Linter::default().with_rule(NoSecrets);
+        assert!(linter.disable("empty-value"));
+        assert!(!linter.disable("unknown"));
+        let diagnostics = linter.lint(&env);
+        assert_eq!(
+            diagnostics[0].to_string(),
+            "error[no-secrets] at line 2: no secrets"
+        );
+    }
+}
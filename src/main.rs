@@ -1,10 +1,17 @@
 use anyhow::Result;
-use clap::{Parser, Subcommand};
+use clap::{Parser, Subcommand, ValueEnum};
+use serde::Deserialize;
 use std::io::{self, Read, Write};
 use std::process;
+use std::time::Duration;
 
-mod env_file;
-use env_file::EnvFile;
+mod config;
+
+use config::Config;
+use envq::{
+    Dialect, DuplicatePolicy, EnvFile, ExpandOptions, FormatOptions, FromEnvValue, KeySyntax,
+    Linter, ParseOptions, Position, QuoteStyle, Url, ValueError,
+};
 
 #[derive(Parser)]
 #[command(name = "envq")]
@@ -12,60 +19,315 @@ use env_file::EnvFile;
 struct Cli {
     #[command(subcommand)]
     command: Commands,
+    /// keep invalid lines as they are instead of failing
+    #[arg(long, global = true, overrides_with = "no_lenient")]
+    lenient: bool,
+    /// fail on invalid lines even if the config sets lenient
+    #[arg(long, global = true, overrides_with = "lenient")]
+    no_lenient: bool,
+    /// which key names are valid [default: those of the dialect]
+    #[arg(long, global = true, value_enum)]
+    keys: Option<Keys>,
+    /// read the file the way this program loads it
+    #[arg(long, global = true, value_enum)]
+    dialect: Option<Dialects>,
 }
 
 #[derive(Subcommand)]
 enum Commands {
     List {
-        /// arguments: [(keys)|values] [file]
+        /// arguments: [(keys)|values|duplicates] [file]
         args: Vec<String>,
+        /// resolve ${VAR} and $VAR references in values
+        #[arg(long)]
+        expand: bool,
+        /// resolve references not defined in the file from the environment
+        #[arg(long, requires = "expand")]
+        env: bool,
     },
     Get {
         /// arguments: [(key)|comment|header] [key] [file]
         args: Vec<String>,
+        /// resolve ${VAR} and $VAR references in the value
+        #[arg(long)]
+        expand: bool,
+        /// resolve references not defined in the file from the environment
+        #[arg(long, requires = "expand")]
+        env: bool,
+        /// which definition of a duplicated key to read the value from [default: first]
+        #[arg(long, value_enum)]
+        duplicates: Option<Duplicates>,
+        /// validate the value as this type and print it normalized
+        #[arg(long = "type", value_enum)]
+        value_type: Option<ValueType>,
     },
     Set {
         /// arguments: [(key)|comment|header] [key] value [file]
         args: Vec<String>,
+        /// add a new key right after this key
+        #[arg(long, value_name = "KEY", conflicts_with_all = ["before", "section"])]
+        after: Option<String>,
+        /// add a new key right before this key
+        #[arg(long, value_name = "KEY", conflicts_with = "section")]
+        before: Option<String>,
+        /// add a new key at the end of the section introduced by this comment
+        #[arg(long, value_name = "NAME")]
+        section: Option<String>,
     },
     Del {
         /// arguments: [(key)|comment|header] [key] [file]
         args: Vec<String>,
     },
+    Rename {
+        old: String,
+        new: String,
+        file: Option<String>,
+        /// replace NEW if it is defined already
+        #[arg(long)]
+        force: bool,
+    },
+    Sort {
+        file: Option<String>,
+        /// file listing keys in the order they should come first, one per line
+        #[arg(long, value_name = "FILE")]
+        order: Option<String>,
+    },
+    Fmt {
+        file: Option<String>,
+        /// only report whether the file is formatted, exits with 1 if it isn't
+        #[arg(long)]
+        check: bool,
+        /// how to quote values [default: minimal]
+        #[arg(long, value_enum)]
+        quote: Option<Quotes>,
+        /// line up the inline comments of consecutive keys
+        #[arg(long, overrides_with = "no_align_comments")]
+        align_comments: bool,
+        #[arg(long, overrides_with = "align_comments")]
+        no_align_comments: bool,
+        /// replace runs of blank lines with a single one
+        #[arg(long, overrides_with = "no_collapse_blank_lines")]
+        collapse_blank_lines: bool,
+        #[arg(long, overrides_with = "collapse_blank_lines")]
+        no_collapse_blank_lines: bool,
+        #[arg(long, overrides_with = "no_uppercase_keys")]
+        uppercase_keys: bool,
+        #[arg(long, overrides_with = "uppercase_keys")]
+        no_uppercase_keys: bool,
+    },
+    Export {
+        /// arguments: [(add)|remove] [file]
+        args: Vec<String>,
+    },
+    Dedupe {
+        /// arguments: [(last)|first] [file]
+        args: Vec<String>,
+    },
+    Check {
+        /// file to check (defaults to stdin)
+        file: Option<String>,
+    },
+    Lint {
+        file: Option<String>,
+        #[arg(long, value_enum, default_value = "text")]
+        format: LintFormat,
+        /// skip a rule, can be repeated
+        #[arg(long, value_name = "RULE")]
+        disable: Vec<String>,
+    },
+}
+
+#[derive(Clone, Copy, ValueEnum, Deserialize)]
+#[serde(rename_all = "lowercase")]
+enum Duplicates {
+    First,
+    Last,
+}
+
+#[derive(Clone, Copy, ValueEnum, Deserialize)]
+#[serde(rename_all = "lowercase")]
+enum Quotes {
+    /// keep the quotes as they are
+    Preserve,
+    /// quote only values that need it
+    Minimal,
+    /// double quote every value
+    Double,
+}
+
+impl From<Quotes> for QuoteStyle {
+    fn from(quotes: Quotes) -> Self {
+        match quotes {
+            Quotes::Preserve => QuoteStyle::Preserve,
+            Quotes::Minimal => QuoteStyle::Minimal,
+            Quotes::Double => QuoteStyle::Double,
+        }
+    }
+}
+
+#[derive(Clone, Copy, ValueEnum, Deserialize)]
+#[serde(rename_all = "lowercase")]
+enum Keys {
+    /// letters, digits and underscores, not starting with a digit
+    Posix,
+    /// like posix, with dots and dashes
+    Compose,
+    /// anything without whitespace or `=`, not starting with `#`
+    Permissive,
+}
+
+impl From<Keys> for KeySyntax {
+    fn from(keys: Keys) -> Self {
+        match keys {
+            Keys::Posix => KeySyntax::Posix,
+            Keys::Compose => KeySyntax::Compose,
+            Keys::Permissive => KeySyntax::Permissive,
+        }
+    }
+}
+
+#[derive(Clone, Copy, ValueEnum, Deserialize)]
+#[serde(rename_all = "kebab-case")]
+enum Dialects {
+    /// envq's own rules
+    Envq,
+    /// `set -a; source .env` in bash
+    Bash,
+    /// docker compose
+    Compose,
+    /// systemd EnvironmentFile=
+    Systemd,
+    PythonDotenv,
+    /// the dotenv npm package
+    Node,
+}
+
+impl From<Dialects> for Dialect {
+    fn from(dialects: Dialects) -> Self {
+        match dialects {
+            Dialects::Envq => Dialect::Envq,
+            Dialects::Bash => Dialect::Bash,
+            Dialects::Compose => Dialect::Compose,
+            Dialects::Systemd => Dialect::Systemd,
+            Dialects::PythonDotenv => Dialect::PythonDotenv,
+            Dialects::Node => Dialect::Node,
+        }
+    }
+}
+
+#[derive(Clone, Copy, ValueEnum)]
+enum LintFormat {
+    Text,
+    Json,
+}
+
+#[derive(Clone, Copy, ValueEnum)]
+enum ValueType {
+    Int,
+    Float,
+    Bool,
+    /// printed in seconds
+    Duration,
+    Json,
+    /// comma separated, printed one item per line
+    List,
+    /// printed normalized
+    Url,
+}
+
+impl From<Duplicates> for DuplicatePolicy {
+    fn from(duplicates: Duplicates) -> Self {
+        match duplicates {
+            Duplicates::First => DuplicatePolicy::First,
+            Duplicates::Last => DuplicatePolicy::Last,
+        }
+    }
+}
+
+// a `--flag` and `--no-flag` pair, `None` leaves the choice to the config
+fn flag(yes: bool, no: bool) -> Option<bool> {
+    match (yes, no) {
+        (true, _) => Some(true),
+        (_, true) => Some(false),
+        _ => None,
+    }
 }
 
 fn main() -> Result<()> {
     let cli = Cli::parse();
+    let lenient = flag(cli.lenient, cli.no_lenient);
 
     match cli.command {
-        Commands::List { args } => {
+        Commands::List { args, expand, env } => {
             let (list_mode, file) = parse_list_args(&args)?;
-            let content = read_input(file)?;
-            let env_file = EnvFile::parse(&content)?;
+            let config = Config::find(file)?;
+            let env_file =
+                read_env_file(file, &config.parse_options(lenient, cli.keys, cli.dialect))?;
             match list_mode {
                 ListMode::Keys => {
                     for key in env_file.list_keys() {
                         println!("{}", key);
                     }
                 }
+                ListMode::Values if expand || env_file.dialect().interpolates() => {
+                    let mut options = ExpandOptions::default();
+                    options.env = env;
+                    for (key, value) in env_file.expand(&options)? {
+                        println!("{}={}", key, value);
+                    }
+                }
                 ListMode::Values => {
-                    for key in env_file.list_keys() {
-                        if let Some(value) = env_file.get_value(key) {
-                            println!("{}={}", key, value);
-                        }
+                    for (key, value) in env_file.list_values() {
+                        println!("{}={}", key, value);
+                    }
+                }
+                ListMode::Duplicates => {
+                    let duplicates = env_file.duplicates();
+                    for (key, lines) in &duplicates {
+                        let lines: Vec<String> = lines.iter().map(|l| l.to_string()).collect();
+                        println!("{}: lines {}", key, lines.join(", "));
+                    }
+                    if !duplicates.is_empty() {
+                        process::exit(1);
                     }
                 }
             }
         }
-        Commands::Get { args } => {
+        Commands::Get {
+            args,
+            expand,
+            env,
+            duplicates,
+            value_type,
+        } => {
             let (target, file) = parse_get_del_args(&args)?;
-            let content = read_input(file)?;
-            let env_file = EnvFile::parse(&content)?;
+            if value_type.is_some() && !matches!(target, Target::Key(_)) {
+                anyhow::bail!("--type only applies to values");
+            }
+            let config = Config::find(file)?;
+            let env_file =
+                read_env_file(file, &config.parse_options(lenient, cli.keys, cli.dialect))?;
+            // what the loader of the dialect would see, unless asked for otherwise
+            let policy = duplicates
+                .or(config.duplicates)
+                .map(DuplicatePolicy::from)
+                .unwrap_or(env_file.dialect().duplicate_policy());
 
             let found = match target {
                 Target::Key(key) => {
-                    if let Some(value) = env_file.get_value(key) {
-                        println!("{}", value);
+                    let value = if expand || env_file.dialect().interpolates() {
+                        let mut options = ExpandOptions::default();
+                        options.env = env;
+                        options.duplicates = policy;
+                        env_file.get_expanded(key, &options)?
+                    } else {
+                        env_file.get_value_with(key, policy).map(str::to_string)
+                    };
+                    if let Some(value) = value {
+                        match value_type {
+                            Some(value_type) => println!("{}", convert(key, &value, value_type)?),
+                            None => println!("{}", value),
+                        }
                         true
                     } else {
                         false
@@ -94,14 +356,29 @@ fn main() -> Result<()> {
                 process::exit(1);
             }
         }
-        Commands::Set { args } => {
+        Commands::Set {
+            args,
+            after,
+            before,
+            section,
+        } => {
             let (target, value, file) = parse_set_args(&args)?;
-            let content = read_input(file)?;
-            let mut env_file = EnvFile::parse(&content)?;
+            let position = match (&after, &before, &section) {
+                (Some(key), _, _) => Position::After(key),
+                (_, Some(key), _) => Position::Before(key),
+                (_, _, Some(name)) => Position::Section(name),
+                _ => Position::End,
+            };
+            if position != Position::End && !matches!(target, Target::Key(_)) {
+                anyhow::bail!("--after, --before and --section only apply to keys");
+            }
+            let config = Config::find(file)?;
+            let mut env_file =
+                read_env_file(file, &config.parse_options(lenient, cli.keys, cli.dialect))?;
 
             match target {
                 Target::Key(key) => {
-                    env_file.set_value(key, &value);
+                    env_file.set_value_at(key, &value, position)?;
                 }
                 Target::Comment(key) => {
                     env_file.set_comment(key, &value);
@@ -115,8 +392,9 @@ fn main() -> Result<()> {
         }
         Commands::Del { args } => {
             let (target, file) = parse_get_del_args(&args)?;
-            let content = read_input(file)?;
-            let mut env_file = EnvFile::parse(&content)?;
+            let config = Config::find(file)?;
+            let mut env_file =
+                read_env_file(file, &config.parse_options(lenient, cli.keys, cli.dialect))?;
 
             match target {
                 Target::Key(key) => {
@@ -132,6 +410,173 @@ fn main() -> Result<()> {
 
             write_output(file, &env_file.to_string())?;
         }
+        Commands::Rename {
+            old,
+            new,
+            file,
+            force,
+        } => {
+            let file = file.as_deref();
+            let config = Config::find(file)?;
+            let mut env_file =
+                read_env_file(file, &config.parse_options(lenient, cli.keys, cli.dialect))?;
+
+            env_file.rename_key(&old, &new, force)?;
+
+            write_output(file, &env_file.to_string())?;
+        }
+        Commands::Sort { file, order } => {
+            let file = file.as_deref();
+            let config = Config::find(file)?;
+            let mut env_file =
+                read_env_file(file, &config.parse_options(lenient, cli.keys, cli.dialect))?;
+
+            match order {
+                Some(order_path) => {
+                    let content = std::fs::read_to_string(&order_path)?;
+                    env_file.sort_with_order(&parse_order(&content));
+                }
+                None => env_file.sort(),
+            }
+
+            write_output(file, &env_file.to_string())?;
+        }
+        Commands::Fmt {
+            file,
+            check,
+            quote,
+            align_comments,
+            no_align_comments,
+            collapse_blank_lines,
+            no_collapse_blank_lines,
+            uppercase_keys,
+            no_uppercase_keys,
+        } => {
+            let file = file.as_deref();
+            let config = Config::find(file)?;
+            let content = read_input(file)?;
+            let mut env_file = parse_env_file(
+                &content,
+                file,
+                &config.parse_options(lenient, cli.keys, cli.dialect),
+            )?;
+
+            // `format` keeps the quotes of other dialects, don't pretend otherwise
+            let quote = quote.or(config.format.quote);
+            if env_file.dialect() != Dialect::Envq
+                && quote.is_some_and(|quote| !matches!(quote, Quotes::Preserve))
+            {
+                anyhow::bail!(
+                    "--quote only applies to the envq dialect, other dialects keep their quotes"
+                );
+            }
+            let mut options = FormatOptions::default();
+            options.quote_style = quote.unwrap_or(Quotes::Minimal).into();
+            options.align_comments =
+                flag(align_comments, no_align_comments).unwrap_or(config.format.align_comments);
+            options.collapse_blank_lines = flag(collapse_blank_lines, no_collapse_blank_lines)
+                .unwrap_or(config.format.collapse_blank_lines);
+            options.uppercase_keys =
+                flag(uppercase_keys, no_uppercase_keys).unwrap_or(config.format.uppercase_keys);
+            env_file.format(&options);
+            let formatted = env_file.to_string();
+
+            if check {
+                if formatted != content {
+                    eprintln!("{} is not formatted", file.unwrap_or("<stdin>"));
+                    process::exit(1);
+                }
+            } else {
+                write_output(file, &formatted)?;
+            }
+        }
+        Commands::Export { args } => {
+            let (export, file) = parse_export_args(&args)?;
+            let config = Config::find(file)?;
+            let mut env_file =
+                read_env_file(file, &config.parse_options(lenient, cli.keys, cli.dialect))?;
+
+            env_file.set_export(export);
+
+            write_output(file, &env_file.to_string())?;
+        }
+        Commands::Dedupe { args } => {
+            let (policy, file) = parse_dedupe_args(&args)?;
+            let config = Config::find(file)?;
+            let mut env_file =
+                read_env_file(file, &config.parse_options(lenient, cli.keys, cli.dialect))?;
+
+            let policy = policy
+                .or(config.duplicates.map(DuplicatePolicy::from))
+                .unwrap_or(DuplicatePolicy::Last);
+            env_file.dedupe(policy);
+
+            write_output(file, &env_file.to_string())?;
+        }
+        Commands::Check { file } => {
+            let file = file.as_deref();
+            let config = Config::find(file)?;
+            let content = read_input(file)?;
+            let options = config.parse_options(lenient, cli.keys, cli.dialect);
+            let (_, errors) = EnvFile::parse_lenient_with(&content, &options);
+
+            let diagnostics: Vec<String> = errors
+                .iter()
+                .map(|e| e.diagnostic(file.unwrap_or("<stdin>")))
+                .collect();
+            if !diagnostics.is_empty() {
+                println!("{}", diagnostics.join("\n\n"));
+                process::exit(1);
+            }
+        }
+        Commands::Lint {
+            file,
+            format,
+            disable,
+        } => {
+            let file = file.as_deref();
+            let config = Config::find(file)?;
+            let env_file =
+                read_env_file(file, &config.parse_options(lenient, cli.keys, cli.dialect))?;
+
+            let mut linter = Linter::default();
+            for rule in disable.iter().chain(&config.lint.disable) {
+                if !linter.disable(rule) {
+                    anyhow::bail!("Unknown lint rule: {}", rule);
+                }
+            }
+            let diagnostics = linter.lint(&env_file);
+
+            let path = file.unwrap_or("<stdin>");
+            match format {
+                LintFormat::Text => {
+                    for d in &diagnostics {
+                        println!(
+                            "{}:{}: {}[{}]: {}",
+                            path, d.line, d.severity, d.rule, d.message
+                        );
+                    }
+                }
+                LintFormat::Json => {
+                    let diagnostics: Vec<_> = diagnostics
+                        .iter()
+                        .map(|d| {
+                            serde_json::json!({
+                                "file": path,
+                                "line": d.line,
+                                "rule": d.rule,
+                                "severity": d.severity.to_string(),
+                                "message": d.message,
+                            })
+                        })
+                        .collect();
+                    println!("{}", serde_json::to_string_pretty(&diagnostics)?);
+                }
+            }
+            if !diagnostics.is_empty() {
+                process::exit(1);
+            }
+        }
     }
 
     Ok(())
@@ -140,6 +585,7 @@ fn main() -> Result<()> {
 enum ListMode {
     Keys,
     Values,
+    Duplicates,
 }
 
 enum Target<'a> {
@@ -166,6 +612,11 @@ fn parse_list_args(args: &[String]) -> Result<(ListMode, Option<&str>)> {
             let file = args.get(1).map(|s| s.as_str());
             Ok((ListMode::Values, file))
         }
+        "duplicates" => {
+            // envq list duplicates [file]
+            let file = args.get(1).map(|s| s.as_str());
+            Ok((ListMode::Duplicates, file))
+        }
         _ => {
             // envq list [file] (defaults to values mode)
             Ok((ListMode::Values, Some(first)))
@@ -173,6 +624,57 @@ fn parse_list_args(args: &[String]) -> Result<(ListMode, Option<&str>)> {
     }
 }
 
+fn parse_export_args(args: &[String]) -> Result<(bool, Option<&str>)> {
+    if args.is_empty() {
+        // envq export (defaults to add)
+        return Ok((true, None));
+    }
+
+    let first = args[0].as_str();
+    match first {
+        "add" => {
+            // envq export add [file]
+            let file = args.get(1).map(|s| s.as_str());
+            Ok((true, file))
+        }
+        "remove" => {
+            // envq export remove [file]
+            let file = args.get(1).map(|s| s.as_str());
+            Ok((false, file))
+        }
+        _ => {
+            // envq export [file] (defaults to add)
+            Ok((true, Some(first)))
+        }
+    }
+}
+
+// the policy is `None` when it's left to the config, which defaults to last
+fn parse_dedupe_args(args: &[String]) -> Result<(Option<DuplicatePolicy>, Option<&str>)> {
+    if args.is_empty() {
+        // envq dedupe
+        return Ok((None, None));
+    }
+
+    let first = args[0].as_str();
+    match first {
+        "last" => {
+            // envq dedupe last [file]
+            let file = args.get(1).map(|s| s.as_str());
+            Ok((Some(DuplicatePolicy::Last), file))
+        }
+        "first" => {
+            // envq dedupe first [file]
+            let file = args.get(1).map(|s| s.as_str());
+            Ok((Some(DuplicatePolicy::First), file))
+        }
+        _ => {
+            // envq dedupe [file]
+            Ok((None, Some(first)))
+        }
+    }
+}
+
 fn parse_get_del_args(args: &[String]) -> Result<(Target<'_>, Option<&str>)> {
     if args.is_empty() {
         return Err(anyhow::anyhow!(
@@ -277,6 +779,41 @@ fn parse_set_args(args: &[String]) -> Result<(Target<'_>, String, Option<&str>)>
     }
 }
 
+// one key per line, so an `.env.example` works as an order file as well
+fn parse_order(content: &str) -> Vec<&str> {
+    content
+        .lines()
+        .map(str::trim)
+        .filter(|line| !line.is_empty() && !line.starts_with('#'))
+        .map(|line| {
+            let key = line.split('=').next().unwrap_or_default().trim();
+            key.strip_prefix("export ").unwrap_or(key).trim()
+        })
+        .collect()
+}
+
+// validates a value and returns it the way scripts can rely on
+fn convert(key: &str, value: &str, value_type: ValueType) -> Result<String> {
+    let converted = match value_type {
+        ValueType::Int => i64::from_env_value(value).map(|n| n.to_string()),
+        ValueType::Float => f64::from_env_value(value).map(|n| n.to_string()),
+        ValueType::Bool => bool::from_env_value(value).map(|b| b.to_string()),
+        ValueType::Duration => Duration::from_env_value(value).map(|d| d.as_secs_f64().to_string()),
+        ValueType::Json => serde_json::from_str::<serde_json::Value>(value)
+            .map(|json| json.to_string())
+            .map_err(|e| format!("expected JSON, got '{}' ({})", value, e)),
+        ValueType::List => Vec::<String>::from_env_value(value).map(|items| items.join("\n")),
+        ValueType::Url => Url::from_env_value(value).map(|url| url.to_string()),
+    };
+    converted.map_err(|message| {
+        ValueError {
+            key: key.to_string(),
+            message,
+        }
+        .into()
+    })
+}
+
 fn read_input(file_path: Option<&str>) -> Result<String> {
     match file_path {
         Some(path) => Ok(std::fs::read_to_string(path)?),
@@ -292,6 +829,20 @@ fn read_input(file_path: Option<&str>) -> Result<String> {
     }
 }
 
+fn read_env_file(file_path: Option<&str>, options: &ParseOptions) -> Result<EnvFile> {
+    let content = read_input(file_path)?;
+    parse_env_file(&content, file_path, options)
+}
+
+fn parse_env_file(
+    content: &str,
+    file_path: Option<&str>,
+    options: &ParseOptions,
+) -> Result<EnvFile> {
+    EnvFile::parse_with(content, options)
+        .map_err(|e| anyhow::anyhow!(e.diagnostic(file_path.unwrap_or("<stdin>"))))
+}
+
 fn write_output(file_path: Option<&str>, content: &str) -> Result<()> {
     match file_path {
         Some(path) => {
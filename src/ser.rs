@@ -0,0 +1,86 @@
+use serde::ser::{Serialize, SerializeMap, SerializeSeq, Serializer};
+
+use crate::env_file::{Entry, EnvFile, Quote};
+
+// an env file serializes to the list of its lines:
+// [{"type": "comment", "text": "# header"}, {"type": "key_value", "key": "A", ...}]
+impl Serialize for EnvFile {
+    fn serialize<S: Serializer>(&self, serializer: S) -> Result<S::Ok, S::Error> {
+        let mut seq = serializer.serialize_seq(Some(self.entries().len()))?;
+        for entry in self.entries() {
+            seq.serialize_element(entry)?;
+        }
+        seq.end()
+    }
+}
+
+impl Serialize for Entry {
+    fn serialize<S: Serializer>(&self, serializer: S) -> Result<S::Ok, S::Error> {
+        let mut map = serializer.serialize_map(None)?;
+        match self {
+            Entry::KeyValue {
+                export,
+                key,
+                value,
+                quote,
+                comment,
+                ..
+            } => {
+                map.serialize_entry("type", "key_value")?;
+                map.serialize_entry("key", key)?;
+                map.serialize_entry("value", value)?;
+                map.serialize_entry("quote", quote)?;
+                map.serialize_entry("export", export)?;
+                map.serialize_entry("comment", comment)?;
+            }
+            Entry::Comment(text) => {
+                map.serialize_entry("type", "comment")?;
+                map.serialize_entry("text", text)?;
+            }
+            Entry::Blank(_) => {
+                map.serialize_entry("type", "blank")?;
+            }
+            Entry::Invalid(text) => {
+                map.serialize_entry("type", "invalid")?;
+                map.serialize_entry("text", text)?;
+            }
+        }
+        map.end()
+    }
+}
+
+impl Serialize for Quote {
+    fn serialize<S: Serializer>(&self, serializer: S) -> Result<S::Ok, S::Error> {
+        serializer.serialize_str(match self {
+            Quote::None => "none",
+            Quote::Single => "single",
+            Quote::Double => "double",
+            Quote::Backtick => "backtick",
+        })
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_serialize_entries() {
+        let env = EnvFile::parse("# header\n\nexport A='1' # one\n").unwrap();
+        assert_eq!(
+            serde_json::to_value(&env).unwrap(),
+            serde_json::json!([
+                {"type": "comment", "text": "# header"},
+                {"type": "blank"},
+                {
+                    "type": "key_value",
+                    "key": "A",
+                    "value": "1",
+                    "quote": "single",
+                    "export": true,
+                    "comment": "one"
+                }
+            ])
+        );
+    }
+}
@@ -0,0 +1,155 @@
+use std::cmp::Ordering;
+use std::collections::HashMap;
+
+use crate::env_file::{Entry, EnvFile};
+
+impl EnvFile {
+    /// Sorts the keys of every blank-line-separated section alphabetically.
+    pub fn sort(&mut self) {
+        self.sort_with_order(&[]);
+    }
+
+    /// Sorts the keys of every section, keys listed in `order` come first and
+    /// in that order, the others follow alphabetically.
+    ///
+    /// Comment lines right above a key move with it. The header stays at the
+    /// top, and so do comments separated from the keys by a blank line, like
+    /// section titles. Sections with invalid lines are left alone.
+    pub fn sort_with_order(&mut self, order: &[&str]) {
+        let ranks: HashMap<&str, usize> = order
+            .iter()
+            .enumerate()
+            .map(|(rank, key)| (*key, rank))
+            .collect();
+        let compare = |a: &str, b: &str| match (ranks.get(a), ranks.get(b)) {
+            (Some(a), Some(b)) => a.cmp(b),
+            (Some(_), None) => Ordering::Less,
+            (None, Some(_)) => Ordering::Greater,
+            (None, None) => a.cmp(b),
+        };
+
+        let mut entries = self.entries().to_vec();
+        let mut start = self.header_end();
+        while start < entries.len() {
+            let end = entries[start..]
+                .iter()
+                .position(|entry| matches!(entry, Entry::Blank(_)))
+                .map_or(entries.len(), |pos| start + pos);
+            sort_section(&mut entries[start..end], &compare);
+            start = end + 1;
+        }
+        self.set_entries(entries);
+    }
+}
+
+fn sort_section(section: &mut [Entry], compare: &impl Fn(&str, &str) -> Ordering) {
+    if section
+        .iter()
+        .any(|entry| matches!(entry, Entry::Invalid(_)))
+    {
+        return;
+    }
+
+    // split into runs of comments, each run ending with the key it documents
+    let mut blocks: Vec<&[Entry]> = Vec::new();
+    let mut block_start = 0;
+    for (index, entry) in section.iter().enumerate() {
+        if let Entry::KeyValue { .. } = entry {
+            blocks.push(&section[block_start..=index]);
+            block_start = index + 1;
+        }
+    }
+    let trailing = &section[block_start..];
+
+    blocks.sort_by(|a, b| compare(block_key(a), block_key(b)));
+
+    let sorted: Vec<Entry> = blocks
+        .into_iter()
+        .flatten()
+        .chain(trailing)
+        .cloned()
+        .collect();
+    section.clone_from_slice(&sorted);
+}
+
+fn block_key(block: &[Entry]) -> &str {
+    block.last().and_then(Entry::key).unwrap_or_default()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_sort_within_sections() {
+        let content = "# header\n\nC=3\nA=1 # one\nB=2\n\nZ=26\nY=25\n";
+        let mut env = EnvFile::parse(content).unwrap();
+        env.sort();
+        assert_eq!(
+            env.to_string(),
+            "# header\n\nA=1 # one\nB=2\nC=3\n\nY=25\nZ=26\n"
+        );
+    }
+
+    #[test]
+    fn test_comments_move_with_keys() {
+        let content = "# header\n\n# the port\nPORT=1\n# the host\n# is local\nHOST=localhost\n";
+        let mut env = EnvFile::parse(content).unwrap();
+        env.sort();
+        assert_eq!(
+            env.to_string(),
+            "# header\n\n# the host\n# is local\nHOST=localhost\n# the port\nPORT=1\n"
+        );
+    }
+
+    #[test]
+    fn test_section_title_stays() {
+        let content = "A=1\n\n# Database\n\nDB_PORT=5432\nDB_HOST=localhost\n# end\n";
+        let mut env = EnvFile::parse(content).unwrap();
+        env.sort();
+        assert_eq!(
+            env.to_string(),
+            "A=1\n\n# Database\n\nDB_HOST=localhost\nDB_PORT=5432\n# end\n"
+        );
+    }
+
+    #[test]
+    fn test_comment_above_first_key_moves_with_it() {
+        let content = "A=1\n\n# deprecated, remove soon\nZED=1\nBAR=2\n";
+        let mut env = EnvFile::parse(content).unwrap();
+        env.sort();
+        assert_eq!(
+            env.to_string(),
+            "A=1\n\nBAR=2\n# deprecated, remove soon\nZED=1\n"
+        );
+    }
+
+    #[test]
+    fn test_header_without_blank_line_stays() {
+        let mut env = EnvFile::parse("# header\nB=2\nA=1\n").unwrap();
+        env.sort();
+        assert_eq!(env.to_string(), "# header\nA=1\nB=2\n");
+    }
+
+    #[test]
+    fn test_sort_with_order() {
+        let mut env = EnvFile::parse("D=4\nC=3\nB=2\nA=1\n").unwrap();
+        env.sort_with_order(&["C", "A"]);
+        assert_eq!(env.to_string(), "C=3\nA=1\nB=2\nD=4\n");
+    }
+
+    #[test]
+    fn test_duplicates_keep_their_order() {
+        let mut env = EnvFile::parse("B=1\nA=2\nB=3\n").unwrap();
+        env.sort();
+        assert_eq!(env.to_string(), "A=2\nB=1\nB=3\n");
+        assert_eq!(env.get_value("B"), Some("1"));
+    }
+
+    #[test]
+    fn test_section_with_invalid_lines_is_left_alone() {
+        let (mut env, _) = EnvFile::parse_lenient("B=2\nbroken\nA=1\n\nD=4\nC=3\n");
+        env.sort();
+        assert_eq!(env.to_string(), "B=2\nbroken\nA=1\n\nC=3\nD=4\n");
+    }
+}
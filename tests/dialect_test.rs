@@ -0,0 +1,55 @@
+use std::collections::BTreeMap;
+use std::fs;
+use std::path::Path;
+
+use envq::{Dialect, EnvFile, ExpandOptions, ParseOptions};
+
+// every fixture in tests/fixtures/dialects comes with the values its loader
+// produces, envq has to read the same values with the matching dialect
+fn check_fixture(name: &str, dialect: Dialect) {
+    let dir = Path::new(env!("CARGO_MANIFEST_DIR")).join("tests/fixtures/dialects");
+    let content = fs::read_to_string(dir.join(format!("{name}.env"))).unwrap();
+    let expected: BTreeMap<String, String> =
+        serde_json::from_str(&fs::read_to_string(dir.join(format!("{name}.json"))).unwrap())
+            .unwrap();
+
+    let mut options = ParseOptions::default();
+    options.dialect = dialect;
+    let env = EnvFile::parse_with(&content, &options).unwrap();
+    let actual: BTreeMap<String, String> = env
+        .expand(&ExpandOptions::default())
+        .unwrap()
+        .into_iter()
+        .map(|(key, value)| (key.to_string(), value))
+        .collect();
+
+    for (key, value) in &expected {
+        assert_eq!(actual.get(key), Some(value), "{name}: {key}");
+    }
+    assert_eq!(actual.len(), expected.len(), "{name}: keys");
+}
+
+#[test]
+fn test_bash() {
+    check_fixture("bash", Dialect::Bash);
+}
+
+#[test]
+fn test_python_dotenv() {
+    check_fixture("python-dotenv", Dialect::PythonDotenv);
+}
+
+#[test]
+fn test_compose() {
+    check_fixture("compose", Dialect::Compose);
+}
+
+#[test]
+fn test_systemd() {
+    check_fixture("systemd", Dialect::Systemd);
+}
+
+#[test]
+fn test_node() {
+    check_fixture("node", Dialect::Node);
+}
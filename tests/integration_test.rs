@@ -111,6 +111,80 @@ fn test_get_comment_with_piped_input_key_not_found() {
         .code(1);
 }
 
+#[test]
+fn test_get_quoted_value_with_piped_input() {
+    envq_cmd()
+        .arg("get")
+        .arg("FOO")
+        .write_stdin("FOO=\"hello world\"\nBAR='single'\n")
+        .assert()
+        .success()
+        .stdout("hello world\n");
+}
+
+#[test]
+fn test_get_multiline_value_with_file() {
+    let dir = TempDir::new().unwrap();
+    let file_path = dir.path().join("test.env");
+    fs::write(
+        &file_path,
+        "TLS_KEY=\"-----BEGIN KEY-----\nabc\n-----END KEY-----\"\nFOO=bar\n",
+    )
+    .unwrap();
+
+    envq_cmd()
+        .arg("get")
+        .arg("TLS_KEY")
+        .arg(&file_path)
+        .assert()
+        .success()
+        .stdout("-----BEGIN KEY-----\nabc\n-----END KEY-----\n");
+}
+
+#[test]
+fn test_set_multiline_value_with_file() {
+    let dir = TempDir::new().unwrap();
+    let file_path = create_test_env(&dir);
+
+    envq_cmd()
+        .arg("set")
+        .arg("TLS_KEY")
+        .arg("line1\nline2")
+        .arg(&file_path)
+        .assert()
+        .success();
+
+    envq_cmd()
+        .arg("get")
+        .arg("TLS_KEY")
+        .arg(&file_path)
+        .assert()
+        .success()
+        .stdout("line1\nline2\n");
+}
+
+#[test]
+fn test_set_value_with_quotes_reads_back() {
+    let dir = TempDir::new().unwrap();
+    let file_path = create_test_env(&dir);
+
+    envq_cmd()
+        .arg("set")
+        .arg("FOO")
+        .arg("say \"hi\" # now")
+        .arg(&file_path)
+        .assert()
+        .success();
+
+    envq_cmd()
+        .arg("get")
+        .arg("FOO")
+        .arg(&file_path)
+        .assert()
+        .success()
+        .stdout("say \"hi\" # now\n");
+}
+
 #[test]
 fn test_list_default_shows_values() {
     let dir = TempDir::new().unwrap();
@@ -397,6 +471,26 @@ fn test_set_with_stdin_output() {
         .stdout(predicate::str::contains("FOO=newvalue"));
 }
 
+#[test]
+fn test_set_preserves_hash_in_other_values() {
+    let dir = TempDir::new().unwrap();
+    let file_path = dir.path().join("test.env");
+    fs::write(&file_path, "DB_PASSWORD=abc#123\nFOO=bar\n").unwrap();
+
+    envq_cmd()
+        .arg("set")
+        .arg("FOO")
+        .arg("baz")
+        .arg(&file_path)
+        .assert()
+        .success();
+
+    assert_eq!(
+        fs::read_to_string(&file_path).unwrap(),
+        "DB_PASSWORD=abc#123\nFOO=baz\n"
+    );
+}
+
 #[test]
 fn test_del_with_stdin_output() {
     envq_cmd()
@@ -408,3 +502,1121 @@ fn test_del_with_stdin_output() {
         .stdout(predicate::str::contains("BAR=baz"))
         .stdout(predicate::str::contains("FOO=").not());
 }
+
+#[test]
+fn test_get_exported_key() {
+    envq_cmd()
+        .arg("get")
+        .arg("FOO")
+        .write_stdin("export FOO=bar\n")
+        .assert()
+        .success()
+        .stdout("bar\n");
+}
+
+#[test]
+fn test_export_add_and_remove_with_file() {
+    let dir = TempDir::new().unwrap();
+    let file_path = dir.path().join("test.env");
+    fs::write(
+        &file_path,
+        "# header\n\nFOO=bar\nexport BAR=baz # comment\n",
+    )
+    .unwrap();
+
+    envq_cmd().arg("export").arg(&file_path).assert().success();
+
+    assert_eq!(
+        fs::read_to_string(&file_path).unwrap(),
+        "# header\n\nexport FOO=bar\nexport BAR=baz # comment\n"
+    );
+
+    envq_cmd()
+        .arg("export")
+        .arg("remove")
+        .arg(&file_path)
+        .assert()
+        .success();
+
+    assert_eq!(
+        fs::read_to_string(&file_path).unwrap(),
+        "# header\n\nFOO=bar\nBAR=baz # comment\n"
+    );
+}
+
+#[test]
+fn test_set_same_value_keeps_file_identical() {
+    let dir = TempDir::new().unwrap();
+    let file_path = dir.path().join("test.env");
+    let content = "\n#header\n\n  FOO = bar   #comment\nexport  BAR=\"baz\"\n";
+    fs::write(&file_path, content).unwrap();
+
+    envq_cmd()
+        .arg("set")
+        .arg("FOO")
+        .arg("bar")
+        .arg(&file_path)
+        .assert()
+        .success();
+
+    assert_eq!(fs::read_to_string(&file_path).unwrap(), content);
+}
+
+#[test]
+fn test_get_expand_resolves_references() {
+    envq_cmd()
+        .arg("get")
+        .arg("--expand")
+        .arg("URL")
+        .write_stdin("USER=admin\nHOST=localhost\nURL=postgres://${USER}@$HOST/app\n")
+        .assert()
+        .success()
+        .stdout("postgres://admin@localhost/app\n");
+}
+
+#[test]
+fn test_get_without_expand_is_raw() {
+    envq_cmd()
+        .arg("get")
+        .arg("URL")
+        .write_stdin("HOST=localhost\nURL=http://${HOST}\n")
+        .assert()
+        .success()
+        .stdout("http://${HOST}\n");
+}
+
+#[test]
+fn test_get_expand_env_fallback() {
+    envq_cmd()
+        .arg("get")
+        .arg("URL")
+        .arg("--expand")
+        .arg("--env")
+        .env("ENVQ_TEST_HOST", "example.com")
+        .write_stdin("URL=http://${ENVQ_TEST_HOST}\n")
+        .assert()
+        .success()
+        .stdout("http://example.com\n");
+
+    envq_cmd()
+        .arg("get")
+        .arg("URL")
+        .arg("--expand")
+        .env("ENVQ_TEST_HOST", "example.com")
+        .write_stdin("URL=http://${ENVQ_TEST_HOST}\n")
+        .assert()
+        .success()
+        .stdout("http://\n");
+}
+
+#[test]
+fn test_list_expand() {
+    envq_cmd()
+        .arg("list")
+        .arg("--expand")
+        .write_stdin("A=1\nB=${A}2\nC='${A}'\n")
+        .assert()
+        .success()
+        .stdout("A=1\nB=12\nC=${A}\n");
+}
+
+#[test]
+fn test_list_expand_ignores_later_keys() {
+    envq_cmd()
+        .arg("list")
+        .arg("--expand")
+        .write_stdin("A=${B}1\nB=${A}2\n")
+        .assert()
+        .success()
+        .stdout("A=1\nB=12\n");
+}
+
+#[test]
+fn test_get_expand_default_and_error_operators() {
+    envq_cmd()
+        .arg("get")
+        .arg("--expand")
+        .arg("URL")
+        .write_stdin("URL=http://${HOST:-localhost}:${PORT-8080}\n")
+        .assert()
+        .success()
+        .stdout("http://localhost:8080\n");
+
+    envq_cmd()
+        .arg("get")
+        .arg("--expand")
+        .arg("URL")
+        .write_stdin("URL=http://${HOST:?set HOST first}\n")
+        .assert()
+        .failure()
+        .stderr(predicate::str::contains(
+            "Required variable HOST is not set: set HOST first",
+        ));
+}
+
+#[test]
+fn test_get_duplicates_first_and_last() {
+    envq_cmd()
+        .arg("get")
+        .arg("A")
+        .write_stdin("A=1\nB=2\nA=3\n")
+        .assert()
+        .success()
+        .stdout("1\n");
+
+    envq_cmd()
+        .arg("get")
+        .arg("A")
+        .arg("--duplicates")
+        .arg("last")
+        .write_stdin("A=1\nB=2\nA=3\n")
+        .assert()
+        .success()
+        .stdout("3\n");
+}
+
+#[test]
+fn test_list_duplicates() {
+    envq_cmd()
+        .arg("list")
+        .arg("duplicates")
+        .write_stdin("A=1\nB=2\nA=3\nB=4\nC=5\n")
+        .assert()
+        .failure()
+        .code(1)
+        .stdout("A: lines 1, 3\nB: lines 2, 4\n");
+
+    envq_cmd()
+        .arg("list")
+        .arg("duplicates")
+        .write_stdin("A=1\nB=2\n")
+        .assert()
+        .success()
+        .stdout("");
+}
+
+#[test]
+fn test_list_values_shows_each_duplicate() {
+    envq_cmd()
+        .arg("list")
+        .write_stdin("A=1\nA=2\n")
+        .assert()
+        .success()
+        .stdout("A=1\nA=2\n");
+}
+
+#[test]
+fn test_dedupe_with_file() {
+    let dir = TempDir::new().unwrap();
+    let file_path = dir.path().join("test.env");
+    fs::write(&file_path, "A=1\nB=2 # comment\nA=3\nB=4\n").unwrap();
+
+    envq_cmd()
+        .arg("dedupe")
+        .arg("first")
+        .arg(&file_path)
+        .assert()
+        .success();
+
+    assert_eq!(
+        fs::read_to_string(&file_path).unwrap(),
+        "A=1\nB=2 # comment\n"
+    );
+}
+
+#[test]
+fn test_dedupe_defaults_to_last() {
+    envq_cmd()
+        .arg("dedupe")
+        .write_stdin("A=1\nB=2\nA=3\n")
+        .assert()
+        .success()
+        .stdout("B=2\nA=3\n");
+}
+
+#[test]
+fn test_parse_error_diagnostic_with_file() {
+    let dir = TempDir::new().unwrap();
+    let file_path = dir.path().join("test.env");
+    fs::write(&file_path, "FOO=bar\nBAR='baz' oops\n").unwrap();
+
+    envq_cmd()
+        .arg("get")
+        .arg("FOO")
+        .arg(&file_path)
+        .assert()
+        .failure()
+        .stderr(predicate::str::contains(format!(
+            "Unexpected text after quoted value\n --> {}:2:11\n  |\n2 | BAR='baz' oops\n  |           ^",
+            file_path.display()
+        )));
+}
+
+#[test]
+fn test_parse_error_diagnostic_with_stdin() {
+    envq_cmd()
+        .arg("list")
+        .write_stdin("FOO=bar\ninvalid\n")
+        .assert()
+        .failure()
+        .stderr(predicate::str::contains(" --> <stdin>:2:1"));
+}
+
+#[test]
+fn test_lenient_get_and_set_in_broken_file() {
+    let dir = TempDir::new().unwrap();
+    let file_path = dir.path().join("test.env");
+    fs::write(&file_path, "FOO=bar\nthis is broken\nBAR=baz\n").unwrap();
+
+    envq_cmd()
+        .arg("get")
+        .arg("BAR")
+        .arg(&file_path)
+        .assert()
+        .failure();
+
+    envq_cmd()
+        .arg("get")
+        .arg("BAR")
+        .arg(&file_path)
+        .arg("--lenient")
+        .assert()
+        .success()
+        .stdout("baz\n");
+
+    envq_cmd()
+        .arg("--lenient")
+        .arg("set")
+        .arg("BAR")
+        .arg("qux")
+        .arg(&file_path)
+        .assert()
+        .success();
+
+    assert_eq!(
+        fs::read_to_string(&file_path).unwrap(),
+        "FOO=bar\nthis is broken\nBAR=qux\n"
+    );
+}
+
+#[test]
+fn test_check_lists_all_problems() {
+    envq_cmd()
+        .arg("check")
+        .write_stdin("FOO=bar\nbroken\nBAR='baz' x\n")
+        .assert()
+        .failure()
+        .code(1)
+        .stdout(predicate::str::contains(" --> <stdin>:2:1"))
+        .stdout(predicate::str::contains(" --> <stdin>:3:11"));
+}
+
+#[test]
+fn test_check_valid_file() {
+    let dir = TempDir::new().unwrap();
+    let file_path = create_test_env(&dir);
+
+    envq_cmd()
+        .arg("check")
+        .arg(&file_path)
+        .assert()
+        .success()
+        .stdout("");
+}
+
+#[test]
+fn test_set_keeps_crlf_and_bom() {
+    let dir = TempDir::new().unwrap();
+    let file_path = dir.path().join("test.env");
+    fs::write(&file_path, "\u{feff}# header\r\nFOO=bar\r\nBAR=baz").unwrap();
+
+    envq_cmd()
+        .arg("set")
+        .arg("FOO")
+        .arg("qux")
+        .arg(&file_path)
+        .assert()
+        .success();
+
+    assert_eq!(
+        fs::read_to_string(&file_path).unwrap(),
+        "\u{feff}# header\r\nFOO=qux\r\nBAR=baz"
+    );
+}
+
+#[test]
+fn test_get_type_normalizes_value() {
+    let cases = [
+        ("int", " 042", "42\n"),
+        ("float", "1.50", "1.5\n"),
+        ("bool", "Yes", "true\n"),
+        ("duration", "1m30s", "90\n"),
+        ("json", "\"{ \\\"a\\\": [1, 2] }\"", "{\"a\":[1,2]}\n"),
+        ("list", "a, b,c", "a\nb\nc\n"),
+        (
+            "url",
+            "HTTPS://Example.com:443/x",
+            "https://example.com/x\n",
+        ),
+    ];
+    for (value_type, value, expected) in cases {
+        envq_cmd()
+            .arg("get")
+            .arg("--type")
+            .arg(value_type)
+            .arg("KEY")
+            .write_stdin(format!("KEY={}\n", value))
+            .assert()
+            .success()
+            .stdout(expected);
+    }
+}
+
+#[test]
+fn test_get_type_error_names_key() {
+    envq_cmd()
+        .arg("get")
+        .arg("--type")
+        .arg("int")
+        .arg("PORT")
+        .write_stdin("PORT=http\n")
+        .assert()
+        .failure()
+        .stderr(predicate::str::contains(
+            "Invalid value for PORT: expected integer, got 'http'",
+        ));
+}
+
+#[test]
+fn test_get_type_url_error() {
+    envq_cmd()
+        .arg("get")
+        .arg("--type")
+        .arg("url")
+        .arg("API")
+        .write_stdin("API=localhost:8080\n")
+        .assert()
+        .failure()
+        .stderr(predicate::str::contains(
+            "Invalid value for API: expected URL like https://example.com, got 'localhost:8080'",
+        ));
+}
+
+#[test]
+fn test_get_type_with_expand() {
+    envq_cmd()
+        .arg("get")
+        .arg("--expand")
+        .arg("--type")
+        .arg("bool")
+        .arg("DEBUG")
+        .write_stdin("ENABLED=on\nDEBUG=${ENABLED}\n")
+        .assert()
+        .success()
+        .stdout("true\n");
+}
+
+#[test]
+fn test_rename_keeps_position_and_comment() {
+    let dir = TempDir::new().unwrap();
+    let file_path = dir.path().join("test.env");
+    fs::write(&file_path, "A=1\nOLD=value # comment\nB=2\n").unwrap();
+
+    envq_cmd()
+        .arg("rename")
+        .arg("OLD")
+        .arg("NEW")
+        .arg(&file_path)
+        .assert()
+        .success();
+
+    assert_eq!(
+        fs::read_to_string(&file_path).unwrap(),
+        "A=1\nNEW=value # comment\nB=2\n"
+    );
+}
+
+#[test]
+fn test_rename_existing_key_needs_force() {
+    envq_cmd()
+        .arg("rename")
+        .arg("A")
+        .arg("B")
+        .write_stdin("A=1\nB=2\n")
+        .assert()
+        .failure()
+        .stderr(predicate::str::contains("Key already exists: B"));
+
+    envq_cmd()
+        .arg("rename")
+        .arg("--force")
+        .arg("A")
+        .arg("B")
+        .write_stdin("A=1\nB=2\n")
+        .assert()
+        .success()
+        .stdout("B=1\n");
+}
+
+#[test]
+fn test_rename_missing_key() {
+    envq_cmd()
+        .arg("rename")
+        .arg("MISSING")
+        .arg("NEW")
+        .write_stdin("A=1\n")
+        .assert()
+        .failure()
+        .stderr(predicate::str::contains("Key not found: MISSING"));
+}
+
+#[test]
+fn test_set_after_and_before() {
+    envq_cmd()
+        .arg("set")
+        .arg("NEW")
+        .arg("1")
+        .arg("--after")
+        .arg("A")
+        .write_stdin("A=a\nB=b\n")
+        .assert()
+        .success()
+        .stdout("A=a\nNEW=1\nB=b\n");
+
+    envq_cmd()
+        .arg("set")
+        .arg("NEW")
+        .arg("1")
+        .arg("--before")
+        .arg("A")
+        .write_stdin("A=a\nB=b\n")
+        .assert()
+        .success()
+        .stdout("NEW=1\nA=a\nB=b\n");
+
+    // comments above the key stay with it
+    envq_cmd()
+        .arg("set")
+        .arg("--before")
+        .arg("PORT")
+        .arg("NEW")
+        .arg("x")
+        .write_stdin("A=1\n# the port\nPORT=1\n")
+        .assert()
+        .success()
+        .stdout("A=1\nNEW=x\n# the port\nPORT=1\n");
+}
+
+#[test]
+fn test_set_section() {
+    let dir = TempDir::new().unwrap();
+    let file_path = dir.path().join("test.env");
+    fs::write(
+        &file_path,
+        "# Database\nDB_HOST=localhost\n\n# Cache\nREDIS_URL=redis://\n",
+    )
+    .unwrap();
+
+    envq_cmd()
+        .arg("set")
+        .arg("DB_PORT")
+        .arg("5432")
+        .arg("--section")
+        .arg("Database")
+        .arg(&file_path)
+        .assert()
+        .success();
+
+    assert_eq!(
+        fs::read_to_string(&file_path).unwrap(),
+        "# Database\nDB_HOST=localhost\nDB_PORT=5432\n\n# Cache\nREDIS_URL=redis://\n"
+    );
+}
+
+#[test]
+fn test_set_position_errors() {
+    envq_cmd()
+        .arg("set")
+        .arg("NEW")
+        .arg("1")
+        .arg("--after")
+        .arg("MISSING")
+        .write_stdin("A=a\n")
+        .assert()
+        .failure()
+        .stderr(predicate::str::contains("Key not found: MISSING"));
+
+    envq_cmd()
+        .arg("set")
+        .arg("NEW")
+        .arg("1")
+        .arg("--section")
+        .arg("Mail")
+        .write_stdin("A=a\n")
+        .assert()
+        .failure()
+        .stderr(predicate::str::contains("Section not found: Mail"));
+
+    // the position only matters for a new key
+    envq_cmd()
+        .arg("set")
+        .arg("--after")
+        .arg("MISSING")
+        .arg("A")
+        .arg("2")
+        .write_stdin("A=1\n")
+        .assert()
+        .success()
+        .stdout("A=2\n");
+}
+
+#[test]
+fn test_sort() {
+    let dir = TempDir::new().unwrap();
+    let file_path = dir.path().join("test.env");
+    fs::write(
+        &file_path,
+        "# header\n\n# Database\n\nDB_USER=admin\nDB_HOST=localhost # the host\n\n# the second\nB=2\n# the first\nA=1\n",
+    )
+    .unwrap();
+
+    envq_cmd().arg("sort").arg(&file_path).assert().success();
+
+    assert_eq!(
+        fs::read_to_string(&file_path).unwrap(),
+        "# header\n\n# Database\n\nDB_HOST=localhost # the host\nDB_USER=admin\n\n# the first\nA=1\n# the second\nB=2\n"
+    );
+}
+
+#[test]
+fn test_sort_with_order_file() {
+    let dir = TempDir::new().unwrap();
+    let order_path = dir.path().join(".env.example");
+    fs::write(&order_path, "# keys\nexport C=\nA=example\n").unwrap();
+
+    envq_cmd()
+        .arg("sort")
+        .arg("--order")
+        .arg(&order_path)
+        .write_stdin("A=1\nB=2\nC=3\n")
+        .assert()
+        .success()
+        .stdout("C=3\nA=1\nB=2\n");
+}
+
+#[test]
+fn test_fmt() {
+    let dir = TempDir::new().unwrap();
+    let file_path = dir.path().join("test.env");
+    fs::write(
+        &file_path,
+        "  A = \"1\"   #one\nLONG_KEY='two' # two\n\n\n\nb=3",
+    )
+    .unwrap();
+
+    envq_cmd()
+        .arg("fmt")
+        .arg("--align-comments")
+        .arg("--collapse-blank-lines")
+        .arg("--uppercase-keys")
+        .arg(&file_path)
+        .assert()
+        .success();
+
+    assert_eq!(
+        fs::read_to_string(&file_path).unwrap(),
+        "A=1          # one\nLONG_KEY=two # two\n\nB=3\n"
+    );
+}
+
+#[test]
+fn test_fmt_quote_style() {
+    envq_cmd()
+        .arg("fmt")
+        .arg("--quote")
+        .arg("double")
+        .write_stdin("A=1\nB='x'\n")
+        .assert()
+        .success()
+        .stdout("A=\"1\"\nB=\"x\"\n");
+}
+
+#[test]
+fn test_fmt_and_set_keep_spaces_quoted() {
+    envq_cmd()
+        .arg("fmt")
+        .write_stdin("A=\"foo bar\"\n")
+        .assert()
+        .success()
+        .stdout("A=\"foo bar\"\n");
+
+    envq_cmd()
+        .arg("set")
+        .arg("NEW")
+        .arg("hello world")
+        .write_stdin("")
+        .assert()
+        .success()
+        .stdout("NEW=\"hello world\"\n");
+}
+
+#[test]
+fn test_fmt_and_set_keep_empty_values_quoted() {
+    // the way the empty-value rule asks an intentional empty value to be written
+    envq_cmd()
+        .arg("fmt")
+        .write_stdin("EMPTY=\"\"\n")
+        .assert()
+        .success()
+        .stdout("EMPTY=\"\"\n");
+
+    envq_cmd()
+        .arg("set")
+        .arg("EMPTY")
+        .arg("")
+        .write_stdin("")
+        .assert()
+        .success()
+        .stdout("EMPTY=\"\"\n");
+}
+
+#[test]
+fn test_fmt_quote_rejected_for_other_dialects() {
+    envq_cmd()
+        .arg("--dialect")
+        .arg("bash")
+        .arg("fmt")
+        .arg("--check")
+        .arg("--quote")
+        .arg("double")
+        .write_stdin("A=1\n")
+        .assert()
+        .failure()
+        .stderr(predicate::str::contains(
+            "--quote only applies to the envq dialect",
+        ));
+
+    // quotes stay as they are by default
+    envq_cmd()
+        .arg("--dialect")
+        .arg("bash")
+        .arg("fmt")
+        .write_stdin("A='1'  \n")
+        .assert()
+        .success()
+        .stdout("A='1'\n");
+}
+
+#[test]
+fn test_fmt_check() {
+    let dir = TempDir::new().unwrap();
+    let file_path = dir.path().join("test.env");
+    fs::write(&file_path, "A = 1\n").unwrap();
+
+    envq_cmd()
+        .arg("fmt")
+        .arg("--check")
+        .arg(&file_path)
+        .assert()
+        .failure()
+        .code(1)
+        .stderr(predicate::str::contains("is not formatted"));
+    // check mode never writes
+    assert_eq!(fs::read_to_string(&file_path).unwrap(), "A = 1\n");
+
+    envq_cmd()
+        .arg("fmt")
+        .arg("--check")
+        .write_stdin("A=1\n")
+        .assert()
+        .success()
+        .stdout("");
+}
+
+#[test]
+fn test_config_applies_from_parent_directory() {
+    let dir = TempDir::new().unwrap();
+    fs::write(
+        dir.path().join(".envq.toml"),
+        "duplicates = \"last\"\n\n[format]\nquote = \"double\"\n",
+    )
+    .unwrap();
+    let sub_dir = dir.path().join("config");
+    fs::create_dir(&sub_dir).unwrap();
+    let file_path = sub_dir.join("test.env");
+    fs::write(&file_path, "A=1\nA=2\n").unwrap();
+
+    envq_cmd()
+        .arg("get")
+        .arg("A")
+        .arg(&file_path)
+        .assert()
+        .success()
+        .stdout("2\n");
+
+    // flags take precedence over the config
+    envq_cmd()
+        .arg("get")
+        .arg("--duplicates")
+        .arg("first")
+        .arg("A")
+        .arg(&file_path)
+        .assert()
+        .success()
+        .stdout("1\n");
+
+    envq_cmd().arg("fmt").arg(&file_path).assert().success();
+    assert_eq!(
+        fs::read_to_string(&file_path).unwrap(),
+        "A=\"1\"\nA=\"2\"\n"
+    );
+}
+
+#[test]
+fn test_config_for_stdin_uses_working_directory() {
+    let dir = TempDir::new().unwrap();
+    fs::write(dir.path().join(".envq.toml"), "lenient = true\n").unwrap();
+
+    envq_cmd()
+        .current_dir(dir.path())
+        .arg("get")
+        .arg("A")
+        .write_stdin("broken\nA=1\n")
+        .assert()
+        .success()
+        .stdout("1\n");
+}
+
+#[test]
+fn test_no_flags_override_config() {
+    let dir = TempDir::new().unwrap();
+    fs::write(
+        dir.path().join(".envq.toml"),
+        "lenient = true\n\n[format]\nalign_comments = true\ncollapse_blank_lines = true\nuppercase_keys = true\n",
+    )
+    .unwrap();
+
+    envq_cmd()
+        .current_dir(dir.path())
+        .arg("get")
+        .arg("--no-lenient")
+        .arg("A")
+        .write_stdin("broken\nA=1\n")
+        .assert()
+        .failure()
+        .stderr(predicate::str::contains("Invalid line before first key"));
+
+    envq_cmd()
+        .current_dir(dir.path())
+        .arg("fmt")
+        .arg("--no-align-comments")
+        .arg("--no-collapse-blank-lines")
+        .arg("--no-uppercase-keys")
+        .write_stdin("a=1 # one\n\n\nlong=2 # two\n")
+        .assert()
+        .success()
+        .stdout("a=1 # one\n\n\nlong=2 # two\n");
+
+    // the last of a flag and its negation wins
+    envq_cmd()
+        .current_dir(dir.path())
+        .arg("fmt")
+        .arg("--no-uppercase-keys")
+        .arg("--uppercase-keys")
+        .write_stdin("a=1\n")
+        .assert()
+        .success()
+        .stdout("A=1\n");
+}
+
+#[test]
+fn test_invalid_config() {
+    let dir = TempDir::new().unwrap();
+    fs::write(
+        dir.path().join(".envq.toml"),
+        "[format]\nquotes = \"double\"\n",
+    )
+    .unwrap();
+
+    envq_cmd()
+        .current_dir(dir.path())
+        .arg("list")
+        .write_stdin("A=1\n")
+        .assert()
+        .failure()
+        .stderr(predicate::str::contains("Invalid config"))
+        .stderr(predicate::str::contains("unknown field `quotes`"));
+}
+
+#[test]
+fn test_lint() {
+    let dir = TempDir::new().unwrap();
+    let file_path = dir.path().join("test.env");
+    fs::write(
+        &file_path,
+        "A=1\nA=2\nb=x # envq-ignore: lowercase-key\nC=$D\n",
+    )
+    .unwrap();
+
+    let path = file_path.display();
+    envq_cmd()
+        .arg("lint")
+        .arg(&file_path)
+        .assert()
+        .failure()
+        .code(1)
+        .stdout(format!(
+            "{path}:2: warning[duplicate-key]: A is already defined on line 1\n\
+             {path}:4: warning[undefined-reference]: C references D, which is not defined above it\n"
+        ));
+}
+
+#[test]
+fn test_lint_follows_dialect() {
+    envq_cmd()
+        .arg("--dialect")
+        .arg("systemd")
+        .arg("lint")
+        .write_stdin("A=$HOME\n")
+        .assert()
+        .success();
+
+    envq_cmd()
+        .arg("--dialect")
+        .arg("compose")
+        .arg("lint")
+        .write_stdin("PRICE=$$5\n")
+        .assert()
+        .success();
+}
+
+#[test]
+fn test_lint_json() {
+    let output = envq_cmd()
+        .arg("lint")
+        .arg("--format")
+        .arg("json")
+        .write_stdin("NAME=John Doe\n")
+        .assert()
+        .failure()
+        .get_output()
+        .stdout
+        .clone();
+
+    let json: serde_json::Value = serde_json::from_slice(&output).unwrap();
+    assert_eq!(
+        json,
+        serde_json::json!([{
+            "file": "<stdin>",
+            "line": 1,
+            "rule": "unquoted-space",
+            "severity": "warning",
+            "message": "the value of NAME contains spaces and should be quoted",
+        }])
+    );
+}
+
+#[test]
+fn test_lint_disable() {
+    let dir = TempDir::new().unwrap();
+    fs::write(
+        dir.path().join(".envq.toml"),
+        "[lint]\ndisable = [\"empty-value\"]\n",
+    )
+    .unwrap();
+
+    envq_cmd()
+        .current_dir(dir.path())
+        .arg("lint")
+        .arg("--disable")
+        .arg("lowercase-key")
+        .write_stdin("a=\nB=\"\"\n")
+        .assert()
+        .success()
+        .stdout("");
+
+    envq_cmd()
+        .arg("lint")
+        .arg("--disable")
+        .arg("no-such-rule")
+        .write_stdin("A=1\n")
+        .assert()
+        .failure()
+        .stderr(predicate::str::contains("Unknown lint rule: no-such-rule"));
+}
+
+#[test]
+fn test_set_rejects_invalid_key() {
+    envq_cmd()
+        .arg("set")
+        .arg("MY KEY")
+        .arg("x")
+        .write_stdin("A=1\n")
+        .assert()
+        .failure()
+        .stderr(predicate::str::contains("Invalid key name: MY KEY"));
+
+    // these would be written as a different key or a comment
+    for key in ["X=Y", "#X"] {
+        envq_cmd()
+            .arg("set")
+            .arg(key)
+            .arg("v")
+            .write_stdin("A=1\n")
+            .assert()
+            .failure()
+            .stderr(predicate::str::contains(format!("Invalid key name: {key}")));
+    }
+    envq_cmd()
+        .arg("rename")
+        .arg("A")
+        .arg("B=C")
+        .write_stdin("A=1\n")
+        .assert()
+        .failure()
+        .stderr(predicate::str::contains("Invalid key name: B=C"));
+
+    envq_cmd()
+        .arg("--keys")
+        .arg("posix")
+        .arg("set")
+        .arg("app.name")
+        .arg("x")
+        .write_stdin("A=1\n")
+        .assert()
+        .failure()
+        .stderr(predicate::str::contains("Invalid key name: app.name"));
+}
+
+#[test]
+fn test_keys_option() {
+    envq_cmd()
+        .arg("get")
+        .arg("--keys")
+        .arg("compose")
+        .arg("app.name")
+        .write_stdin("app.name=envq\n")
+        .assert()
+        .success()
+        .stdout("envq\n");
+
+    envq_cmd()
+        .arg("check")
+        .arg("--keys")
+        .arg("posix")
+        .write_stdin("app.name=envq\n")
+        .assert()
+        .failure()
+        .stdout(predicate::str::contains("Invalid key name"))
+        .stdout(predicate::str::contains(" --> <stdin>:1:1"));
+
+    envq_cmd()
+        .arg("lint")
+        .arg("--keys")
+        .arg("compose")
+        .write_stdin("APP.NAME=envq\n")
+        .assert()
+        .success();
+}
+
+#[test]
+fn test_keys_from_config() {
+    let dir = TempDir::new().unwrap();
+    fs::write(dir.path().join(".envq.toml"), "keys = \"posix\"\n").unwrap();
+
+    envq_cmd()
+        .current_dir(dir.path())
+        .arg("list")
+        .write_stdin("1FOO=x\n")
+        .assert()
+        .failure()
+        .stderr(predicate::str::contains("Invalid key name"));
+}
+
+#[test]
+fn test_dialect_option() {
+    // compose interpolates and reads the last definition
+    envq_cmd()
+        .arg("get")
+        .arg("--dialect")
+        .arg("compose")
+        .arg("URL")
+        .write_stdin("HOST=a\nURL=http://${HOST}\nHOST=b\nURL='$$HOST'\n")
+        .assert()
+        .success()
+        .stdout("$$HOST\n");
+
+    envq_cmd()
+        .arg("get")
+        .arg("--dialect")
+        .arg("compose")
+        .arg("URL")
+        .write_stdin("HOST=localhost\nURL=http://${HOST}\n")
+        .assert()
+        .success()
+        .stdout("http://localhost\n");
+
+    envq_cmd()
+        .arg("get")
+        .arg("--dialect")
+        .arg("node")
+        .arg("PASSWORD")
+        .write_stdin("PASSWORD=abc#123\n")
+        .assert()
+        .success()
+        .stdout("abc\n");
+
+    envq_cmd()
+        .arg("check")
+        .arg("--dialect")
+        .arg("bash")
+        .write_stdin("GREETING=hello world\n")
+        .assert()
+        .failure()
+        .stdout(predicate::str::contains("Unquoted shell syntax"));
+}
+
+#[test]
+fn test_dialect_set_reads_back() {
+    // node can't escape quotes inside quotes
+    envq_cmd()
+        .arg("--dialect")
+        .arg("node")
+        .arg("set")
+        .arg("B")
+        .arg("it's \"q\"")
+        .write_stdin("A=1\n")
+        .assert()
+        .success()
+        .stdout("A=1\nB=`it's \"q\"`\n");
+
+    envq_cmd()
+        .arg("--dialect")
+        .arg("node")
+        .arg("set")
+        .arg("B")
+        .arg("a'b\"c`d#")
+        .write_stdin("A=1\n")
+        .assert()
+        .failure()
+        .stderr(predicate::str::contains("The value of B can't be quoted"));
+}
+
+#[test]
+fn test_dialect_from_config() {
+    let dir = TempDir::new().unwrap();
+    fs::write(
+        dir.path().join(".envq.toml"),
+        "dialect = \"python-dotenv\"\n",
+    )
+    .unwrap();
+
+    envq_cmd()
+        .current_dir(dir.path())
+        .arg("list")
+        .arg("values")
+        .write_stdin("A=1\nB='${A}'\nA=2\n")
+        .assert()
+        .success()
+        .stdout("A=1\nB=1\nA=2\n");
+}
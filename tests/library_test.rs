@@ -0,0 +1,98 @@
+use envq::{
+    DuplicatePolicy, Entry, EnvFile, ExpandError, ExpandOptions, KeyEntry, LineEnding,
+    ParseErrorKind, ParseOptions, SerializeOptions,
+};
+
+#[test]
+fn test_parse_edit_and_write() {
+    let mut env = EnvFile::parse("# header\n\nFOO=bar # comment\nBAR='baz'\n").unwrap();
+    env.set_value("FOO", "new value").unwrap();
+    env.set_value("QUX", "1").unwrap();
+    env.delete_key("BAR");
+    assert_eq!(
+        env.to_string(),
+        "# header\n\nFOO=\"new value\" # comment\nQUX=1\n"
+    );
+}
+
+#[test]
+fn test_parse_error_is_typed() {
+    let error = EnvFile::parse("FOO=bar\nBAR=\"open\n").unwrap_err();
+    assert_eq!(error.kind, ParseErrorKind::UnterminatedQuote);
+    assert_eq!((error.line, error.column), (2, 5));
+}
+
+#[test]
+fn test_parse_options_and_lenient_entries() {
+    let mut options = ParseOptions::default();
+    options.lenient = true;
+    let env = EnvFile::parse_with("FOO=bar\nbroken\n", &options).unwrap();
+    assert_eq!(env.list_keys(), vec!["FOO"]);
+
+    let (_, errors) = EnvFile::parse_lenient("FOO=bar\nbroken\n");
+    assert_eq!(errors.len(), 1);
+}
+
+#[test]
+fn test_expand_and_duplicates() {
+    let env = EnvFile::parse("HOST=a\nURL=http://${HOST}\nHOST=b\n").unwrap();
+    let mut options = ExpandOptions::default();
+    options.duplicates = DuplicatePolicy::Last;
+    assert_eq!(
+        env.get_expanded("URL", &options).unwrap(),
+        Some("http://a".to_string())
+    );
+    assert_eq!(env.get_value_with("HOST", DuplicatePolicy::Last), Some("b"));
+}
+
+#[test]
+fn test_expand_error_is_typed() {
+    let env = EnvFile::parse("URL=${HOST:?set the host}\nBAD=${HOST\n").unwrap();
+    let options = ExpandOptions::default();
+    assert_eq!(
+        env.get_expanded("URL", &options).unwrap_err(),
+        ExpandError::RequiredVariable {
+            name: "HOST".to_string(),
+            message: Some("set the host".to_string()),
+        }
+    );
+    assert!(matches!(
+        env.get_expanded("BAD", &options),
+        Err(ExpandError::UnterminatedReference(_))
+    ));
+}
+
+#[test]
+fn test_serialize_options() {
+    let mut env = EnvFile::parse("FOO=bar\r\n").unwrap();
+    assert_eq!(env.serialize_options().line_ending, LineEnding::CrLf);
+    env.set_serialize_options(SerializeOptions::default());
+    assert_eq!(env.to_string(), "FOO=bar\n");
+}
+
+#[test]
+fn test_entry_display() {
+    let entry = Entry::Comment("# comment".to_string());
+    assert_eq!(entry.to_string(), "# comment");
+}
+
+#[test]
+fn test_entry_api() {
+    let mut env = EnvFile::parse("FOO=bar # comment\n").unwrap();
+    env.entry("FOO")
+        .or_insert("default")
+        .unwrap()
+        .set_value("baz")
+        .unwrap();
+    env.entry("NEW")
+        .or_insert("default")
+        .unwrap()
+        .set_comment("added");
+    if let KeyEntry::Occupied(mut entry) = env.entry("NEW") {
+        entry.move_to(0);
+    }
+    assert_eq!(env.to_string(), "NEW=default # added\nFOO=baz # comment\n");
+
+    let keys: Vec<_> = env.iter().map(|entry| entry.key()).collect();
+    assert_eq!(keys, vec!["NEW", "FOO"]);
+}